@@ -0,0 +1,165 @@
+//! Members of `ar` archives, i.e. static libraries, in the GNU and BSD variants
+//! and GNU thin archives
+
+use crate::elf::{c_str, ReadAt};
+use std::{
+    fs::File,
+    io::{Error, ErrorKind, Result},
+    path::Path,
+};
+
+pub const AR_MAGIC: [u8; 8] = *b"!<arch>\n";
+/// Thin archives only store the paths of their members, relative to the archive
+pub const THIN_MAGIC: [u8; 8] = *b"!<thin>\n";
+
+const HEADER_SIZE: u64 = 60;
+
+/// A member of an archive and where its data is
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    pub offset: u64,
+    pub size: u64,
+    /// member of a thin archive, whose data is the file `name` relative to the archive
+    pub external: bool,
+}
+
+/// `size` bytes at `offset` of another reader
+pub struct Slice<R> {
+    reader: R,
+    offset: u64,
+    size: u64,
+}
+
+impl<R: ReadAt> ReadAt for Slice<R> {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<()> {
+        match offset.checked_add(buf.len() as u64) {
+            Some(end) if end <= self.size => self.reader.read_exact_at(buf, self.offset + offset),
+            _ => Err(Error::from(ErrorKind::UnexpectedEof)),
+        }
+    }
+
+    fn size(&self) -> Result<u64> {
+        Ok(self.size)
+    }
+}
+
+impl Slice<File> {
+    /// The whole file
+    pub fn file(file: File) -> Result<Self> {
+        let size = file.size()?;
+        Ok(Slice {
+            reader: file,
+            offset: 0,
+            size,
+        })
+    }
+
+    /// The data of a member of this archive, which is stored at `path`
+    pub fn member(&self, path: &Path, member: &Member) -> Result<Self> {
+        if member.external {
+            let dir = path.parent().unwrap_or(Path::new("/"));
+            return Slice::file(File::open(dir.join(&member.name))?);
+        }
+        Ok(Slice {
+            reader: self.reader.try_clone()?,
+            offset: self.offset + member.offset,
+            size: member.size,
+        })
+    }
+}
+
+/// `/usr/lib64/libfoo.a(foo.o)` => (`/usr/lib64/libfoo.a`, `foo.o`), like binutils prints them
+pub fn split_path(path: &str) -> Option<(&str, &str)> {
+    let (archive, member) = path.strip_suffix(')')?.split_once(".a(")?;
+    Some((&path[..archive.len() + 2], member))
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Members of an archive without the symbol table and the GNU long name table,
+/// fails with `InvalidData` or `UnexpectedEof` if this isn't a well-formed archive
+pub fn members<R: ReadAt>(reader: &R) -> Result<Vec<Member>> {
+    let size = reader.size()?;
+    let mut magic = [0u8; 8];
+    reader.read_exact_at(&mut magic, 0)?;
+    let thin = match magic {
+        AR_MAGIC => false,
+        THIN_MAGIC => true,
+        _ => return Err(invalid("bad ar magic")),
+    };
+    let mut members = Vec::new();
+    // `//` member of GNU archives, `name/\n` entries referenced by `/offset` names
+    let mut long_names = Vec::new();
+    let mut offset = AR_MAGIC.len() as u64;
+    while offset + HEADER_SIZE <= size {
+        let mut header = [0u8; HEADER_SIZE as usize];
+        reader.read_exact_at(&mut header, offset)?;
+        if header[58..] != *b"`\n" {
+            return Err(invalid("bad ar member header"));
+        }
+        let field = |start: usize, end: usize| String::from_utf8_lossy(&header[start..end]);
+        let name = field(0, 16).trim_end().to_owned();
+        let len: u64 = field(48, 58)
+            .trim_end()
+            .parse()
+            .map_err(|_| invalid("bad ar member size"))?;
+        let data = offset + HEADER_SIZE;
+        // thin archives store the symbol table and long names, but no member data
+        let external = thin && !matches!(name.as_str(), "/" | "/SYM64/" | "//");
+        let end = match external {
+            true => data,
+            false => data
+                .checked_add(len)
+                .filter(|&end| end <= size)
+                .ok_or_else(|| Error::from(ErrorKind::UnexpectedEof))?,
+        };
+        // member data is 2 byte aligned
+        offset = end + end % 2;
+        let (name, skip) = match name.as_str() {
+            "/" | "/SYM64/" => continue,
+            "//" => {
+                long_names = vec![0u8; len as usize];
+                reader.read_exact_at(&mut long_names, data)?;
+                continue;
+            }
+            _ => {
+                if let Some(n) = name.strip_prefix("#1/") {
+                    // BSD: the name is stored NUL padded in front of the data
+                    let n = n
+                        .parse()
+                        .ok()
+                        .filter(|&n| n <= len)
+                        .ok_or_else(|| invalid("bad ar member name"))?;
+                    let mut buf = vec![0u8; n as usize];
+                    reader.read_exact_at(&mut buf, data)?;
+                    (c_str(&buf, 0), n)
+                } else if let Some(i) = name.strip_prefix('/') {
+                    let i: usize = i.parse().map_err(|_| invalid("bad ar member name"))?;
+                    let name = long_names
+                        .get(i..)
+                        .and_then(|s| s.split(|&b| b == b'\n').next())
+                        .ok_or_else(|| invalid("bad ar member name"))?;
+                    let name = String::from_utf8_lossy(name);
+                    (name.trim_end_matches('/').to_owned(), 0)
+                } else {
+                    // GNU terminates names with `/`, BSD pads them with spaces
+                    (name.strip_suffix('/').unwrap_or(&name).to_owned(), 0)
+                }
+            }
+        };
+        // BSD symbol tables, `__.SYMDEF`, `__.SYMDEF SORTED` or `__.SYMDEF_64`
+        if name.starts_with("__.SYMDEF") {
+            continue;
+        }
+        members.push(Member {
+            name,
+            offset: data + skip,
+            size: len - skip,
+            external,
+        });
+    }
+    Ok(members)
+}
@@ -0,0 +1,115 @@
+//! Which ELF files a system can run, to tell native files from firmware and cross toolchains
+
+use crate::elf::{Class, Elf, Endian};
+use color_eyre::eyre::Context;
+use std::{env, fs::File, str::FromStr};
+
+const EM_SPARC: u16 = 2;
+const EM_386: u16 = 3;
+const EM_68K: u16 = 4;
+const EM_MIPS: u16 = 8;
+const EM_PARISC: u16 = 15;
+const EM_SPARC32PLUS: u16 = 18;
+const EM_PPC: u16 = 20;
+const EM_PPC64: u16 = 21;
+const EM_S390: u16 = 22;
+const EM_ARM: u16 = 40;
+const EM_SH: u16 = 42;
+const EM_SPARCV9: u16 = 43;
+const EM_IA_64: u16 = 50;
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+const EM_RISCV: u16 = 243;
+const EM_LOONGARCH: u16 = 258;
+const EM_ALPHA: u16 = 0x9026;
+
+/// Machine, class and byte order of the files a system runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arch {
+    pub machine: u16,
+    pub class: Class,
+    pub endian: Endian,
+}
+
+impl Arch {
+    /// The architecture binarypkg itself was built for
+    pub fn host() -> Self {
+        let exe = env::current_exe()
+            .context("Failed to find the running executable")
+            .unwrap();
+        let elf = File::open(&exe)
+            .and_then(Elf::parse)
+            .with_context(|| format!("Failed to read file: {}", exe.display()))
+            .unwrap();
+        Arch {
+            machine: elf.machine,
+            class: elf.class,
+            endian: elf.endian,
+        }
+    }
+
+    /// Whether a file is native, including the 32-bit machines of multilib systems,
+    /// e.g. x86 on x86-64
+    pub fn is_native(&self, machine: u16, class: Class, endian: Endian) -> bool {
+        if endian != self.endian {
+            return false;
+        }
+        if machine == self.machine {
+            // x32, MIPS o32 and n32 and 31-bit s390 run next to the 64-bit ABI
+            return class == self.class || matches!(machine, EM_X86_64 | EM_MIPS | EM_S390);
+        }
+        let multilib: &[u16] = match self.machine {
+            EM_X86_64 => &[EM_386],
+            EM_AARCH64 => &[EM_ARM],
+            EM_PPC64 => &[EM_PPC],
+            EM_SPARCV9 => &[EM_SPARC, EM_SPARC32PLUS],
+            _ => &[],
+        };
+        multilib.contains(&machine)
+    }
+}
+
+/// The architecture of a CHOST, e.g. `aarch64-unknown-linux-gnu`
+impl FromStr for Arch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use {Class::*, Endian::*};
+        let (cpu, rest) = s.split_once('-').unwrap_or((s, ""));
+        let (machine, class, endian) = match cpu {
+            "x86_64" if rest.ends_with("x32") => (EM_X86_64, Elf32, Little),
+            "x86_64" => (EM_X86_64, Elf64, Little),
+            "i386" | "i486" | "i586" | "i686" => (EM_386, Elf32, Little),
+            "aarch64" => (EM_AARCH64, Elf64, Little),
+            "aarch64_be" => (EM_AARCH64, Elf64, Big),
+            c if c.starts_with("arm") && c.ends_with("eb") => (EM_ARM, Elf32, Big),
+            c if c.starts_with("arm") => (EM_ARM, Elf32, Little),
+            "powerpc64le" => (EM_PPC64, Elf64, Little),
+            "powerpc64" => (EM_PPC64, Elf64, Big),
+            "powerpc" => (EM_PPC, Elf32, Big),
+            "mips64el" => (EM_MIPS, Elf64, Little),
+            "mips64" => (EM_MIPS, Elf64, Big),
+            "mipsel" => (EM_MIPS, Elf32, Little),
+            "mips" => (EM_MIPS, Elf32, Big),
+            "riscv64" => (EM_RISCV, Elf64, Little),
+            "riscv32" => (EM_RISCV, Elf32, Little),
+            "s390x" => (EM_S390, Elf64, Big),
+            "s390" => (EM_S390, Elf32, Big),
+            "sparc64" => (EM_SPARCV9, Elf64, Big),
+            "sparc" => (EM_SPARC, Elf32, Big),
+            "loongarch64" => (EM_LOONGARCH, Elf64, Little),
+            "alpha" => (EM_ALPHA, Elf64, Little),
+            "ia64" => (EM_IA_64, Elf64, Little),
+            c if c.starts_with("hppa") => (EM_PARISC, Elf32, Big),
+            "m68k" => (EM_68K, Elf32, Big),
+            c if c.starts_with("sh") && c.ends_with("eb") => (EM_SH, Elf32, Big),
+            c if c.starts_with("sh") => (EM_SH, Elf32, Little),
+            _ => return Err(format!("unknown CHOST {s}")),
+        };
+        Ok(Arch {
+            machine,
+            class,
+            endian,
+        })
+    }
+}
@@ -0,0 +1,273 @@
+//! Package atoms of /etc/portage files, e.g. `>=dev-libs/foo-1.2:0::gentoo`
+
+use crate::vdb::split_pf;
+use std::{cmp::Ordering, str::FromStr};
+
+/// `1.2.3b_rc1_p2-r1`
+struct Version<'a> {
+    numbers: Vec<&'a str>,
+    letter: Option<u8>,
+    /// rank and number, ranks are ordered like `_alpha < _beta < _pre < _rc < (none) < _p`
+    suffixes: Vec<(u8, u64)>,
+    revision: u64,
+}
+
+const SUFFIXES: [&str; 5] = ["alpha", "beta", "pre", "rc", "p"];
+const NO_SUFFIX: u8 = 4;
+
+impl<'a> Version<'a> {
+    fn parse(v: &'a str) -> Option<Self> {
+        let (v, revision) = match v.rsplit_once("-r") {
+            Some((v, r)) => (v, r.parse().ok()?),
+            None => (v, 0),
+        };
+        let mut parts = v.split('_');
+        let base = parts.next()?;
+        let (base, letter) = match base.as_bytes().last() {
+            Some(&c) if c.is_ascii_lowercase() => (&base[..base.len() - 1], Some(c)),
+            _ => (base, None),
+        };
+        let suffixes = parts
+            .map(|s| {
+                let rank = SUFFIXES.iter().position(|p| s.starts_with(p))?;
+                let n = &s[SUFFIXES[rank].len()..];
+                // `_p` sorts after no suffix
+                let rank = if rank == 4 { 5 } else { rank as u8 };
+                Some((rank, if n.is_empty() { 0 } else { n.parse().ok()? }))
+            })
+            .collect::<Option<_>>()?;
+        Some(Version {
+            numbers: base.split('.').collect(),
+            letter,
+            suffixes,
+            revision,
+        })
+    }
+}
+
+/// Version comparison of the package manager specification
+fn vercmp(a: &str, b: &str) -> Option<Ordering> {
+    let (a, b) = (Version::parse(a)?, Version::parse(b)?);
+    for (i, (x, y)) in a.numbers.iter().zip(&b.numbers).enumerate() {
+        // components after the first with a leading zero compare like decimals
+        let ord = if i > 0 && (x.starts_with('0') || y.starts_with('0')) {
+            x.trim_end_matches('0').cmp(y.trim_end_matches('0'))
+        } else {
+            x.parse::<u64>().ok()?.cmp(&y.parse().ok()?)
+        };
+        if ord != Ordering::Equal {
+            return Some(ord);
+        }
+    }
+    let suffix = |s: &[(u8, u64)], i: usize| s.get(i).copied().unwrap_or((NO_SUFFIX, 0));
+    let suffixes = a.suffixes.len().max(b.suffixes.len());
+    Some(
+        a.numbers
+            .len()
+            .cmp(&b.numbers.len())
+            .then(a.letter.cmp(&b.letter))
+            .then_with(|| {
+                (0..suffixes)
+                    .map(|i| suffix(&a.suffixes, i).cmp(&suffix(&b.suffixes, i)))
+                    .find(|o| o.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            })
+            .then(a.revision.cmp(&b.revision)),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Lt,
+    Le,
+    Eq,
+    /// `=cat/pn-1.2*`
+    Glob,
+    /// `~cat/pn-1.2`, any revision
+    Rev,
+    Ge,
+    Gt,
+}
+
+#[derive(Debug, Clone)]
+pub struct Atom {
+    /// `*` matches any category or name
+    category: String,
+    name: String,
+    version: Option<(Op, String)>,
+    slot: Option<String>,
+    repo: Option<String>,
+}
+
+impl FromStr for Atom {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rest, repo) = match s.split_once("::") {
+            Some((rest, repo)) => (rest, Some(repo.to_owned())),
+            None => (s, None),
+        };
+        let (rest, slot) = match rest.split_once(':') {
+            // a sub-slot or `=` slot operator doesn't change what is installed
+            Some((rest, slot)) => (
+                rest,
+                Some(slot.split(['/', '=']).next().unwrap().to_owned()),
+            ),
+            None => (rest, None),
+        };
+        let op = [
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+            ("=", Op::Eq),
+            ("~", Op::Rev),
+        ]
+        .into_iter()
+        .find_map(|(prefix, op)| Some((op, rest.strip_prefix(prefix)?)));
+        let (category, pf) = op
+            .map_or(rest, |(_, rest)| rest)
+            .split_once('/')
+            .ok_or_else(|| format!("invalid atom {s}"))?;
+        let (name, version) = match op {
+            Some((op, _)) => {
+                let (pf, op) = match pf.strip_suffix('*') {
+                    Some(pf) if op == Op::Eq => (pf, Op::Glob),
+                    _ => (pf, op),
+                };
+                let (pn, version) = split_pf(pf).ok_or_else(|| format!("invalid atom {s}"))?;
+                (pn, Some((op, version.to_owned())))
+            }
+            None => (pf, None),
+        };
+        Ok(Atom {
+            category: category.to_owned(),
+            name: name.to_owned(),
+            version,
+            slot,
+            repo,
+        })
+    }
+}
+
+impl Atom {
+    /// Whether the atom matches an installed version, `slot` and `repo` are `None` if unknown
+    pub fn matches(
+        &self,
+        atom: &str,
+        version: &str,
+        slot: Option<&str>,
+        repo: Option<&str>,
+    ) -> bool {
+        let Some((category, name)) = atom.split_once('/') else {
+            return false;
+        };
+        let glob = |pattern: &str, s: &str| pattern == "*" || pattern == s;
+        if !glob(&self.category, category) || !glob(&self.name, name) {
+            return false;
+        }
+        if self
+            .slot
+            .as_deref()
+            .is_some_and(|s| slot.is_some_and(|slot| s != slot))
+        {
+            return false;
+        }
+        if self
+            .repo
+            .as_deref()
+            .is_some_and(|r| repo.is_some_and(|repo| r != repo))
+        {
+            return false;
+        }
+        let Some((op, want)) = &self.version else {
+            return true;
+        };
+        match op {
+            Op::Glob => version.starts_with(want.as_str()),
+            Op::Rev => {
+                let base = |v: &str| v.rsplit_once("-r").map_or(v, |(v, _)| v).to_owned();
+                vercmp(&base(version), &base(want)) == Some(Ordering::Equal)
+            }
+            op => vercmp(version, want).is_some_and(|ord| match op {
+                Op::Lt => ord.is_lt(),
+                Op::Le => ord.is_le(),
+                Op::Eq => ord.is_eq(),
+                Op::Ge => ord.is_ge(),
+                Op::Gt => ord.is_gt(),
+                Op::Glob | Op::Rev => unreachable!(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lt(a: &str, b: &str) {
+        assert_eq!(vercmp(a, b), Some(Ordering::Less), "{a} < {b}");
+        assert_eq!(vercmp(b, a), Some(Ordering::Greater), "{b} > {a}");
+    }
+
+    #[test]
+    fn numbers() {
+        lt("1.2", "1.10");
+        lt("1.2", "1.2.0");
+        lt("1.01", "1.1");
+        lt("1.001", "1.01");
+        assert_eq!(vercmp("1.10", "1.010"), Some(Ordering::Greater));
+        assert_eq!(vercmp("1.0", "1.00"), Some(Ordering::Equal));
+        assert_eq!(vercmp("01.2", "1.2"), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn letters() {
+        lt("1.2", "1.2a");
+        lt("1.2a", "1.2b");
+        lt("1.2z", "1.3");
+        lt("1.2b", "1.2.1");
+    }
+
+    #[test]
+    fn suffixes() {
+        lt("1.0_alpha", "1.0_beta");
+        lt("1.0_beta2", "1.0_pre");
+        lt("1.0_pre", "1.0_rc");
+        lt("1.0_rc", "1.0_rc1");
+        lt("1.0_rc9", "1.0_rc10");
+        lt("1.0_rc10", "1.0");
+        lt("1.0", "1.0_p");
+        lt("1.0_p1", "1.0_p2");
+        lt("1.0_p1", "1.0.1_alpha");
+        // another suffix is less than none, unless it is `_p`
+        lt("1.0_rc1_alpha", "1.0_rc1");
+        lt("1.0_rc1", "1.0_rc1_p1");
+        lt("1.0a_rc1", "1.0a");
+    }
+
+    #[test]
+    fn revisions() {
+        lt("1.0", "1.0-r1");
+        lt("1.0-r2", "1.0-r10");
+        lt("1.0-r9", "1.0.1");
+        lt("1.0_rc1-r3", "1.0");
+        assert_eq!(vercmp("1.0-r0", "1.0"), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(vercmp("1.0_foo", "1.0"), None);
+        assert_eq!(vercmp("1.0-rx", "1.0"), None);
+    }
+
+    #[test]
+    fn matches() {
+        let atom = |s: &str| s.parse::<Atom>().unwrap();
+        assert!(atom(">=dev-libs/foo-1.2_rc1").matches("dev-libs/foo", "1.2", None, None));
+        assert!(!atom("<dev-libs/foo-1.2").matches("dev-libs/foo", "1.2_p1", None, None));
+        assert!(atom("~dev-libs/foo-1.2").matches("dev-libs/foo", "1.2-r3", None, None));
+        assert!(atom("=dev-libs/foo-1.2*").matches("dev-libs/foo", "1.2.5", None, None));
+        assert!(!atom("dev-libs/foo:2").matches("dev-libs/foo", "1.2", Some("1"), None));
+    }
+}
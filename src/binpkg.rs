@@ -0,0 +1,146 @@
+//! Where an installed package comes from: built locally or installed from a binary package,
+//! of a binhost of our own or of the official Gentoo binhost
+
+use crate::{root::Root, vdb::Entry};
+use clap::ArgEnum;
+use color_eyre::eyre::Context;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    io::ErrorKind as ioErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Portage caches the `Packages` index of every binhost at `<host>/<path>/Packages` here
+const BINHOST_CACHE_PATH: &str = "/var/cache/edb/binhost";
+/// Hosts of the official binhost, distfiles.gentoo.org and its CDN
+const GENTOO_DOMAIN: &str = "gentoo.org";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ArgEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    /// built from source on this host
+    Local,
+    /// installed from a binary package of a binhost other than Gentoo's or of PKGDIR
+    Binhost,
+    /// installed from a binary package of the official Gentoo binhost
+    Gentoo,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Origin::Local => "built locally",
+            Origin::Binhost => "from binhost",
+            Origin::Gentoo => "from Gentoo binhost",
+        })
+    }
+}
+
+/// `BUILD_ID` and `MD5` of a binary package in an index
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Binpkg {
+    build_id: Option<String>,
+    md5: Option<String>,
+}
+
+/// Binary packages of the cached indexes of the official Gentoo binhost
+pub struct Index {
+    /// by CPV
+    gentoo: HashMap<String, HashSet<Binpkg>>,
+}
+
+/// Every `Packages` file below `dir`
+fn index_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ioErrorKind::NotFound => return,
+        Err(e) => Err(e)
+            .with_context(|| format!("Failed to read dir: {}", dir.display()))
+            .unwrap(),
+    };
+    for e in entries {
+        let e = e.unwrap();
+        let path = e.path();
+        if e.file_type().unwrap().is_dir() {
+            index_files(&path, files);
+        } else if e.file_name() == "Packages" {
+            files.push(path);
+        }
+    }
+}
+
+/// `CPV` and binary package of every package of an index, which has blocks of `KEY: value`
+/// lines separated by empty lines, the first one is the header
+fn parse_index(index: &str) -> Vec<(String, Binpkg)> {
+    index
+        .split("\n\n")
+        .skip(1)
+        .filter_map(|block| {
+            let mut cpv = None;
+            let mut build_id = None;
+            let mut md5 = None;
+            for line in block.lines() {
+                match line.split_once(": ") {
+                    Some(("CPV", v)) => cpv = Some(v.to_owned()),
+                    Some(("BUILD_ID", v)) => build_id = Some(v.to_owned()),
+                    Some(("MD5", v)) => md5 = Some(v.to_owned()),
+                    _ => {}
+                }
+            }
+            Some((cpv?, Binpkg { build_id, md5 }))
+        })
+        .collect()
+}
+
+impl Index {
+    pub fn read(root: &Root) -> Self {
+        let cache = root.prefixed(BINHOST_CACHE_PATH);
+        let cache = Path::new(&cache);
+        let mut files = Vec::new();
+        index_files(cache, &mut files);
+        let mut gentoo: HashMap<_, HashSet<_>> = HashMap::new();
+        for file in files {
+            let host = file
+                .strip_prefix(cache)
+                .unwrap()
+                .components()
+                .next()
+                .unwrap();
+            let host = host.as_os_str().to_string_lossy();
+            // `user@host:port` of the binhost URL
+            let host = host.rsplit('@').next().unwrap().split(':').next().unwrap();
+            if host != GENTOO_DOMAIN && !host.ends_with(&format!(".{GENTOO_DOMAIN}")) {
+                continue;
+            }
+            let index = fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read file: {}", file.display()))
+                .unwrap();
+            for (cpv, binpkg) in parse_index(&index) {
+                gentoo.entry(cpv).or_default().insert(binpkg);
+            }
+        }
+        Index { gentoo }
+    }
+
+    /// Portage records BUILD_ID and BINPKGMD5 of the binary package an entry was installed
+    /// from, the official binhost is recognized by a matching package in its index
+    pub fn origin(&self, entry: &Entry) -> Origin {
+        let build_id = entry.build_id();
+        let md5 = entry.binpkg_md5();
+        if build_id.is_none() && md5.is_none() {
+            return Origin::Local;
+        }
+        let official = self.gentoo.get(&entry.cpv()).is_some_and(|binpkgs| {
+            binpkgs.iter().any(|b| {
+                (build_id.is_none() || b.build_id == build_id)
+                    && (md5.is_none() || b.md5.is_none() || b.md5 == md5)
+            })
+        });
+        match official {
+            true => Origin::Gentoo,
+            false => Origin::Binhost,
+        }
+    }
+}
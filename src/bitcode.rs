@@ -0,0 +1,320 @@
+//! Just enough LLVM bitcode to read the producer string of the identification block
+
+/// `BC 0xC0DE`
+pub const BITCODE_MAGIC: [u8; 4] = [b'B', b'C', 0xc0, 0xde];
+/// `0x0B17C0DE` little endian, a header in front of the bitcode, e.g. on Darwin
+pub const WRAPPER_MAGIC: [u8; 4] = [0xde, 0xc0, 0x17, 0x0b];
+
+const ENTER_SUBBLOCK: u64 = 1;
+const END_BLOCK: u64 = 0;
+const DEFINE_ABBREV: u64 = 2;
+const UNABBREV_RECORD: u64 = 3;
+
+const BLOCKINFO_BLOCK_ID: u64 = 0;
+const IDENTIFICATION_BLOCK_ID: u64 = 13;
+const IDENTIFICATION_CODE_STRING: u64 = 1;
+
+/// The identification block comes first and is tiny.
+pub const HEAD_SIZE: u64 = 4096;
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Literal(u64),
+    Fixed(u32),
+    Vbr(u32),
+    /// followed by the op of the elements
+    Array,
+    Char6,
+    Blob,
+}
+
+/// Bits are read starting from the least significant one of each byte
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Bits<'_> {
+    fn read(&mut self, n: u32) -> Option<u64> {
+        if n > 64 || self.pos + n as usize > self.data.len() * 8 {
+            return None;
+        }
+        let mut value = 0;
+        for i in 0..n as usize {
+            let bit = self.data[(self.pos + i) / 8] >> ((self.pos + i) % 8) & 1;
+            value |= (bit as u64) << i;
+        }
+        self.pos += n as usize;
+        Some(value)
+    }
+
+    /// Bits left to read
+    fn left(&self) -> u64 {
+        (self.data.len() * 8).saturating_sub(self.pos) as u64
+    }
+
+    fn vbr(&mut self, n: u32) -> Option<u64> {
+        if n < 2 {
+            return None;
+        }
+        let high = 1 << (n - 1);
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let chunk = self.read(n)?;
+            if shift < 64 {
+                value |= (chunk & (high - 1)) << shift;
+            }
+            shift += n - 1;
+            if chunk & high == 0 {
+                return Some(value);
+            }
+        }
+    }
+
+    fn align32(&mut self) {
+        self.pos = self.pos.div_ceil(32) * 32;
+    }
+
+    fn scalar(&mut self, op: Op) -> Option<u64> {
+        match op {
+            Op::Literal(v) => Some(v),
+            Op::Fixed(n) => self.read(n),
+            Op::Vbr(n) => self.vbr(n),
+            Op::Char6 => {
+                let c = self.read(6)? as u8;
+                Some(match c {
+                    0..=25 => b'a' + c,
+                    26..=51 => b'A' + c - 26,
+                    52..=61 => b'0' + c - 52,
+                    62 => b'.',
+                    _ => b'_',
+                } as u64)
+            }
+            Op::Array | Op::Blob => None,
+        }
+    }
+
+    /// Fields of a record written with an abbreviation, the record code first
+    fn abbreviated(&mut self, ops: &[Op]) -> Option<Vec<u64>> {
+        let mut fields = Vec::new();
+        let mut ops = ops.iter();
+        while let Some(&op) = ops.next() {
+            match op {
+                // like LLVM, an array can't be longer than the bits left
+                Op::Array => {
+                    let len = self.vbr(6)?;
+                    if len > self.left() {
+                        return None;
+                    }
+                    let element = *ops.next()?;
+                    for _ in 0..len {
+                        fields.push(self.scalar(element)?);
+                    }
+                }
+                Op::Blob => {
+                    let len = self.vbr(6)?;
+                    if len > self.left() / 8 {
+                        return None;
+                    }
+                    self.align32();
+                    for _ in 0..len {
+                        fields.push(self.read(8)?);
+                    }
+                    self.align32();
+                }
+                op => fields.push(self.scalar(op)?),
+            }
+        }
+        Some(fields)
+    }
+
+    fn define_abbrev(&mut self) -> Option<Vec<Op>> {
+        let n = self.vbr(5)?;
+        let mut ops = Vec::new();
+        for _ in 0..n {
+            let op = if self.read(1)? == 1 {
+                Op::Literal(self.vbr(8)?)
+            } else {
+                // LLVM reads at most 64 bits at once, VBR chunks at most 32
+                match self.read(3)? {
+                    1 => Op::Fixed(width(self.vbr(5)?, 1, 64)?),
+                    2 => Op::Vbr(width(self.vbr(5)?, 2, 32)?),
+                    3 => Op::Array,
+                    4 => Op::Char6,
+                    5 => Op::Blob,
+                    _ => return None,
+                }
+            };
+            ops.push(op);
+        }
+        Some(ops)
+    }
+
+    /// The string record of the identification block, whose abbreviation width is `width`
+    fn identification(&mut self, width: u32) -> Option<String> {
+        let mut abbrevs = Vec::new();
+        loop {
+            let record = match self.read(width)? {
+                END_BLOCK | ENTER_SUBBLOCK => return None,
+                DEFINE_ABBREV => {
+                    abbrevs.push(self.define_abbrev()?);
+                    continue;
+                }
+                UNABBREV_RECORD => {
+                    let code = self.vbr(6)?;
+                    let n = self.vbr(6)?;
+                    let mut fields = vec![code];
+                    for _ in 0..n {
+                        fields.push(self.vbr(6)?);
+                    }
+                    fields
+                }
+                id => self.abbreviated(abbrevs.get(id as usize - 4)?)?,
+            };
+            if record.first() == Some(&IDENTIFICATION_CODE_STRING) {
+                return Some(record[1..].iter().map(|&c| c as u8 as char).collect());
+            }
+        }
+    }
+}
+
+/// `n` if it is a valid width of a field between `min` and `max` bits
+fn width(n: u64, min: u32, max: u32) -> Option<u32> {
+    u32::try_from(n).ok().filter(|n| (min..=max).contains(n))
+}
+
+/// Producer of the bitcode at the start of `data`, e.g. `LLVM17.0.6`,
+/// `None` if it was written before LLVM 4 added the identification block
+pub fn producer(data: &[u8]) -> Option<String> {
+    let data = if data.starts_with(&WRAPPER_MAGIC) {
+        let offset = u32::from_le_bytes(data.get(8..12)?.try_into().unwrap());
+        data.get(offset as usize..)?
+    } else {
+        data
+    };
+    if !data.starts_with(&BITCODE_MAGIC) {
+        return None;
+    }
+    let mut bits = Bits { data, pos: 32 };
+    loop {
+        if bits.read(2)? != ENTER_SUBBLOCK {
+            return None;
+        }
+        let block = bits.vbr(8)?;
+        let width = width(bits.vbr(4)?, 2, 32)?;
+        bits.align32();
+        let words = bits.read(32)? as usize;
+        match block {
+            IDENTIFICATION_BLOCK_ID => return bits.identification(width),
+            BLOCKINFO_BLOCK_ID => bits.pos += words * 32,
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Writer {
+        bits: Vec<bool>,
+    }
+
+    impl Writer {
+        fn fixed(&mut self, value: u64, n: u32) -> &mut Self {
+            self.bits.extend((0..n).map(|i| value >> i & 1 == 1));
+            self
+        }
+
+        fn vbr(&mut self, mut value: u64, n: u32) -> &mut Self {
+            let high = 1 << (n - 1);
+            while value >= high {
+                self.fixed(value & (high - 1) | high, n);
+                value >>= n - 1;
+            }
+            self.fixed(value, n)
+        }
+
+        fn align32(&mut self) -> &mut Self {
+            while !self.bits.len().is_multiple_of(32) {
+                self.bits.push(false);
+            }
+            self
+        }
+
+        /// Bitcode with an identification block of abbreviation width 4
+        /// holding `abbrev` and a record `fields` written with it
+        fn identification(abbrev: impl Fn(&mut Writer), fields: impl Fn(&mut Writer)) -> Vec<u8> {
+            let mut w = Writer::default();
+            w.fixed(u32::from_le_bytes(BITCODE_MAGIC).into(), 32);
+            w.fixed(ENTER_SUBBLOCK, 2).vbr(IDENTIFICATION_BLOCK_ID, 8);
+            w.vbr(4, 4).align32().fixed(0, 32);
+            w.fixed(DEFINE_ABBREV, 4);
+            abbrev(&mut w);
+            w.fixed(4, 4);
+            fields(&mut w);
+            w.fixed(END_BLOCK, 4).align32();
+            w.bits
+                .chunks(8)
+                .map(|b| b.iter().rev().fold(0, |byte, &bit| byte << 1 | bit as u8))
+                .collect()
+        }
+    }
+
+    /// `[literal IDENTIFICATION_CODE_STRING, array of element]`
+    fn string_abbrev(element: impl Fn(&mut Writer)) -> impl Fn(&mut Writer) {
+        move |w| {
+            w.vbr(3, 5);
+            w.fixed(1, 1).vbr(IDENTIFICATION_CODE_STRING, 8);
+            w.fixed(0, 1).fixed(3, 3);
+            element(w);
+        }
+    }
+
+    #[test]
+    fn producer_string() {
+        let data = Writer::identification(
+            string_abbrev(|w| {
+                w.fixed(0, 1).fixed(1, 3).vbr(8, 5);
+            }),
+            |w| {
+                w.vbr(4, 6);
+                for c in b"LLVM" {
+                    w.fixed((*c).into(), 8);
+                }
+            },
+        );
+        assert_eq!(producer(&data).as_deref(), Some("LLVM"));
+    }
+
+    #[test]
+    fn invalid_width() {
+        for (kind, width) in [(1, 0), (1, 65), (2, 1), (2, 33)] {
+            let data = Writer::identification(
+                string_abbrev(|w| {
+                    w.fixed(0, 1).fixed(kind, 3).vbr(width, 5);
+                }),
+                |w| {
+                    w.vbr(0, 6);
+                },
+            );
+            assert_eq!(producer(&data), None, "kind {kind} width {width}");
+        }
+    }
+
+    #[test]
+    fn array_longer_than_data() {
+        // literal elements take no bits at all
+        let data = Writer::identification(
+            string_abbrev(|w| {
+                w.fixed(1, 1).vbr(b'x'.into(), 8);
+            }),
+            |w| {
+                w.vbr(u32::MAX.into(), 6);
+            },
+        );
+        assert_eq!(producer(&data), None);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::elf::{Elf, ReadAt};
+use serde::Serialize;
+use std::{fmt, io::Result};
+
+const EM_386: u16 = 3;
+const EM_X86_64: u16 = 62;
+
+const GNU_PROPERTY_X86_FEATURE_1_AND: u32 = 0xc000_0002;
+const GNU_PROPERTY_X86_FEATURE_1_IBT: u32 = 1 << 0;
+const GNU_PROPERTY_X86_FEATURE_1_SHSTK: u32 = 1 << 1;
+const GNU_PROPERTY_X86_ISA_1_NEEDED: u32 = 0xc000_8002;
+const GNU_PROPERTY_X86_ISA_1_USED: u32 = 0xc001_0002;
+
+/// Highest level of an ISA_1 bitmask, bit 0 is baseline and bits 1-3 are v2 to v4
+fn isa_level(bits: u32) -> Option<u8> {
+    (bits & 0xf != 0).then(|| (32 - (bits & 0xf).leading_zeros()) as u8)
+}
+
+/// CET and x86-64 micro-architecture level from GNU property notes
+#[derive(Debug, Clone, Serialize)]
+pub struct X86Features {
+    /// Indirect Branch Tracking
+    pub ibt: bool,
+    /// Shadow Stack
+    pub shstk: bool,
+    /// 1 for baseline, 2-4 for x86-64-v2 to v4, `None` if not recorded or not x86-64.
+    /// GCC only records the level needed with `-mneeded`, otherwise the crt files leave a
+    /// baseline note behind, so the level the assembler saw used is taken then.
+    pub isa_level: Option<u8>,
+}
+
+/// Features of an x86 or x86-64 ELF file, `None` for other architectures
+pub fn check<R: ReadAt>(elf: &Elf<R>) -> Result<Option<X86Features>> {
+    if elf.machine != EM_386 && elf.machine != EM_X86_64 {
+        return Ok(None);
+    }
+    let mut features = X86Features {
+        ibt: false,
+        shstk: false,
+        isa_level: None,
+    };
+    let mut needed = None;
+    let mut used = None;
+    for (pr_type, data) in elf.gnu_properties()? {
+        if data.len() < 4 {
+            continue;
+        }
+        let bits = elf.endian.u32(&data);
+        match pr_type {
+            GNU_PROPERTY_X86_FEATURE_1_AND => {
+                features.ibt = bits & GNU_PROPERTY_X86_FEATURE_1_IBT != 0;
+                features.shstk = bits & GNU_PROPERTY_X86_FEATURE_1_SHSTK != 0;
+            }
+            GNU_PROPERTY_X86_ISA_1_NEEDED => needed = isa_level(bits),
+            GNU_PROPERTY_X86_ISA_1_USED => used = isa_level(bits),
+            _ => {}
+        }
+    }
+    if elf.machine == EM_X86_64 {
+        features.isa_level = needed.filter(|&level| level > 1).or(used);
+    }
+    Ok(Some(features))
+}
+
+impl fmt::Display for X86Features {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}IBT", if self.ibt { "" } else { "no " })?;
+        write!(f, ", {}SHSTK", if self.shstk { "" } else { "no " })?;
+        match self.isa_level {
+            Some(1) => write!(f, ", x86-64-baseline"),
+            Some(level) => write!(f, ", x86-64-v{level}"),
+            None => Ok(()),
+        }
+    }
+}
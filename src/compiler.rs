@@ -0,0 +1,189 @@
+use crate::{
+    dwarf,
+    elf::{Elf, ReadAt},
+};
+use serde::Serialize;
+use std::{cmp::Ordering, collections::BTreeSet, fmt, io::Result, str::FromStr};
+
+/// A compiler which left its name in `.comment` or `DW_AT_producer`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Compiler {
+    /// `gcc`, `clang`, `rustc` or `go`
+    pub name: &'static str,
+    pub version: String,
+}
+
+impl fmt::Display for Compiler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.version)
+    }
+}
+
+/// `13.2.1_p20240210` => `13.2.1`
+fn leading_version(s: &str) -> Option<&str> {
+    let end = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let v = s[..end].trim_end_matches('.');
+    v.starts_with(|c: char| c.is_ascii_digit()).then_some(v)
+}
+
+/// Skip a leading parenthesized vendor string, e.g. `(Gentoo 13.2.1_p20240210 p13) 13.2.1`
+fn skip_parens(s: &str) -> &str {
+    if !s.starts_with('(') {
+        return s;
+    }
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return s[i + 1..].trim_start();
+                }
+            }
+            _ => {}
+        }
+    }
+    s
+}
+
+impl Compiler {
+    fn new(name: &'static str, version: &str) -> Option<Self> {
+        Some(Compiler {
+            name,
+            version: leading_version(version)?.to_owned(),
+        })
+    }
+
+    /// Parse one `.comment` string or `DW_AT_producer`, e.g.
+    /// `GCC: (Gentoo 13.2.1_p20240210 p13) 13.2.1`, `GNU C17 13.2.1 20240210 -O2`,
+    /// `clang version 17.0.6`, `clang LLVM (rustc version 1.75.0)`, `Go cmd/compile go1.21.5`,
+    /// or the producer of LLVM bitcode, `LLVM17.0.6` or `LLVM17.0.6-rust-1.75.0-stable`
+    pub fn parse(s: &str) -> Option<Self> {
+        if let Some(rest) = s.strip_prefix("GCC: ") {
+            return Compiler::new("gcc", skip_parens(rest));
+        }
+        if let Some((_, rest)) = s.split_once("rustc version ") {
+            return Compiler::new("rustc", rest);
+        }
+        if let Some((_, rest)) = s.split_once("clang version ") {
+            return Compiler::new("clang", rest);
+        }
+        if let Some(rest) = s.strip_prefix("LLVM") {
+            return match rest.split_once("-rust-") {
+                Some((_, rust)) => Compiler::new("rustc", rust),
+                None => Compiler::new("clang", rest),
+            };
+        }
+        if let Some(rest) = s.strip_prefix("Go cmd/compile go") {
+            return Compiler::new("go", rest);
+        }
+        // `GNU AS 2.41` is the assembler, not gcc
+        if let Some(rest) = s.strip_prefix("GNU ").filter(|s| !s.starts_with("AS ")) {
+            let version = rest.split_whitespace().find_map(leading_version)?;
+            return Compiler::new("gcc", version);
+        }
+        None
+    }
+
+    fn version_numbers(&self) -> Vec<u64> {
+        self.version
+            .split('.')
+            .map(|n| n.parse().unwrap_or(0))
+            .collect()
+    }
+}
+
+/// Compilers which produced an ELF file, from `.comment` and DWARF if present
+pub fn compilers<R: ReadAt>(elf: &Elf<R>) -> Result<BTreeSet<Compiler>> {
+    let mut compilers = BTreeSet::new();
+    if let Some(comment) = elf.section_data(".comment")? {
+        compilers.extend(
+            comment
+                .split(|&b| b == 0)
+                .filter_map(|s| Compiler::parse(&String::from_utf8_lossy(s))),
+        );
+    }
+    compilers.extend(
+        dwarf::producers(elf)?
+            .iter()
+            .filter_map(|p| Compiler::parse(p)),
+    );
+    Ok(compilers)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+/// `--built-with` filter, e.g. `gcc`, `gcc<14`, `clang>=17.0`
+#[derive(Debug, Clone)]
+pub struct Requirement {
+    name: String,
+    version: Option<(Op, Vec<u64>)>,
+}
+
+impl FromStr for Requirement {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let Some(i) = s.find(['<', '>', '=', '!']) else {
+            return Ok(Requirement {
+                name: s.to_owned(),
+                version: None,
+            });
+        };
+        let (name, rest) = s.split_at(i);
+        let (op, version) = [
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+            ("=", Op::Eq),
+        ]
+        .into_iter()
+        .find_map(|(prefix, op)| Some((op, rest.strip_prefix(prefix)?)))
+        .ok_or_else(|| format!("invalid operator in {s}"))?;
+        let version = version
+            .split('.')
+            .map(|n| n.parse().map_err(|_| format!("invalid version in {s}")))
+            .collect::<std::result::Result<Vec<u64>, _>>()?;
+        Ok(Requirement {
+            name: name.to_owned(),
+            version: Some((op, version)),
+        })
+    }
+}
+
+impl Requirement {
+    /// Versions are compared up to the precision of the requirement, so `gcc=13` matches `gcc 13.2.1`
+    pub fn matches(&self, compiler: &Compiler) -> bool {
+        if self.name != compiler.name {
+            return false;
+        }
+        let Some((op, version)) = &self.version else {
+            return true;
+        };
+        let mut have = compiler.version_numbers();
+        have.resize(version.len(), 0);
+        let ord = have.cmp(version);
+        match op {
+            Op::Lt => ord == Ordering::Less,
+            Op::Le => ord != Ordering::Greater,
+            Op::Gt => ord == Ordering::Greater,
+            Op::Ge => ord != Ordering::Less,
+            Op::Eq => ord == Ordering::Equal,
+            Op::Ne => ord != Ordering::Equal,
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Files compressed with xz, zstd or gzip, e.g. kernel modules and firmware
+
+use crate::elf::ReadAt;
+use flate2::read::MultiGzDecoder;
+use lzma_rust2::XzReader;
+use ruzstd::decoding::StreamingDecoder;
+use serde::Serialize;
+use std::{
+    fmt,
+    io::{Error, ErrorKind, Read, Result},
+};
+
+const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// File name extensions of compressed files
+pub const EXTENSIONS: [&str; 3] = [".xz", ".zst", ".gz"];
+
+/// Compressed files are decompressed into memory, this only stops decompression bombs.
+const MAX_SIZE: u64 = 1 << 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Xz,
+    Zstd,
+    Gzip,
+}
+
+impl Compression {
+    /// The compression of a file starting with `head`, `None` if it isn't compressed
+    pub fn detect(head: &[u8]) -> Option<Self> {
+        if head.starts_with(&XZ_MAGIC) {
+            Some(Compression::Xz)
+        } else if head.starts_with(&ZSTD_MAGIC) {
+            Some(Compression::Zstd)
+        } else if head.starts_with(&GZIP_MAGIC) {
+            Some(Compression::Gzip)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compression::Xz => "xz",
+            Compression::Zstd => "zstd",
+            Compression::Gzip => "gzip",
+        })
+    }
+}
+
+/// Reads a `ReadAt` from the start, for the decoders
+struct Cursor<'a, R> {
+    reader: &'a R,
+    pos: u64,
+}
+
+impl<R: ReadAt> Read for Cursor<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = (buf.len() as u64).min(self.reader.size()?.saturating_sub(self.pos));
+        self.reader
+            .read_exact_at(&mut buf[..len as usize], self.pos)?;
+        self.pos += len;
+        Ok(len as usize)
+    }
+}
+
+/// Up to `limit` bytes of the decompressed data, a corrupt stream fails with `InvalidData`
+fn decompress<'a>(reader: impl Read + 'a, compression: Compression, limit: u64) -> Result<Vec<u8>> {
+    let decoder: Box<dyn Read + 'a> = match compression {
+        Compression::Xz => Box::new(XzReader::new(reader, true)),
+        Compression::Zstd => Box::new(
+            StreamingDecoder::new(reader).map_err(|e| Error::new(ErrorKind::InvalidData, e))?,
+        ),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+    };
+    let mut data = Vec::new();
+    decoder
+        .take(limit)
+        .read_to_end(&mut data)
+        .map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof | ErrorKind::InvalidData => e,
+            _ => Error::new(ErrorKind::InvalidData, e),
+        })?;
+    Ok(data)
+}
+
+/// The compression of `reader` and the first `len` bytes of the decompressed data,
+/// `None` if it isn't compressed. Only as much of the stream as needed is decompressed.
+pub fn probe<R: ReadAt>(reader: &R, len: u64) -> Result<Option<(Compression, Vec<u8>)>> {
+    let mut head = vec![0u8; reader.size()?.min(XZ_MAGIC.len() as u64) as usize];
+    reader.read_exact_at(&mut head, 0)?;
+    let Some(compression) = Compression::detect(&head) else {
+        return Ok(None);
+    };
+    let data = decompress(Cursor { reader, pos: 0 }, compression, len)?;
+    Ok(Some((compression, data)))
+}
+
+/// A file, decompressed into memory if it is compressed
+pub enum Input<R> {
+    Plain(R),
+    Decompressed(Vec<u8>),
+}
+
+impl<R: ReadAt> Input<R> {
+    pub fn new(reader: R) -> Result<Self> {
+        Ok(match probe(&reader, MAX_SIZE)? {
+            Some((_, data)) => Input::Decompressed(data),
+            None => Input::Plain(reader),
+        })
+    }
+}
+
+impl<R: ReadAt> ReadAt for Input<R> {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<()> {
+        match self {
+            Input::Plain(reader) => reader.read_exact_at(buf, offset),
+            Input::Decompressed(data) => data[..].read_exact_at(buf, offset),
+        }
+    }
+
+    fn size(&self) -> Result<u64> {
+        match self {
+            Input::Plain(reader) => reader.size(),
+            Input::Decompressed(data) => Ok(data.len() as u64),
+        }
+    }
+}
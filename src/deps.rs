@@ -0,0 +1,208 @@
+//! Rebuild order from the dependencies portage recorded in the VDB
+
+use std::collections::{HashMap, HashSet};
+
+/// Dependencies between the packages of `index`, given as CAT/PN, where `deps[i]` are the
+/// direct dependencies of package `i`. Paths through other packages, whose dependencies are in
+/// `installed`, count as well, e.g. through virtuals, which never have elf files.
+pub fn between<'a>(
+    deps: impl IntoIterator<Item = &'a Vec<String>>,
+    index: &HashMap<&str, usize>,
+    installed: &HashMap<String, Vec<String>>,
+) -> Vec<Vec<usize>> {
+    deps.into_iter()
+        .map(|deps| {
+            let mut found = Vec::new();
+            let mut seen = HashSet::new();
+            let mut stack: Vec<&str> = deps.iter().map(|d| d.as_str()).collect();
+            while let Some(d) = stack.pop() {
+                match index.get(d) {
+                    // its own dependencies are followed from there
+                    Some(&i) => found.push(i),
+                    None if seen.insert(d) => {
+                        stack.extend(installed.get(d).into_iter().flatten().map(|d| d.as_str()))
+                    }
+                    None => {}
+                }
+            }
+            found.sort_unstable();
+            found.dedup();
+            found
+        })
+        .collect()
+}
+
+/// Tarjan's strongly connected components of the graph where `deps[i]` are the nodes `i`
+/// depends on. Components come after every component they depend on.
+fn components(deps: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct State<'a> {
+        deps: &'a [Vec<usize>],
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next: usize,
+        components: Vec<Vec<usize>>,
+    }
+
+    fn visit(s: &mut State, v: usize) {
+        s.index[v] = Some(s.next);
+        s.low[v] = s.next;
+        s.next += 1;
+        s.stack.push(v);
+        s.on_stack[v] = true;
+        for &w in &s.deps[v] {
+            match s.index[w] {
+                None => {
+                    visit(s, w);
+                    s.low[v] = s.low[v].min(s.low[w]);
+                }
+                Some(index) if s.on_stack[w] => s.low[v] = s.low[v].min(index),
+                _ => {}
+            }
+        }
+        if Some(s.low[v]) == s.index[v] {
+            let mut component = Vec::new();
+            while let Some(w) = s.stack.pop() {
+                s.on_stack[w] = false;
+                component.push(w);
+                if w == v {
+                    break;
+                }
+            }
+            component.sort_unstable();
+            s.components.push(component);
+        }
+    }
+
+    let n = deps.len();
+    let mut state = State {
+        deps,
+        index: vec![None; n],
+        low: vec![0; n],
+        on_stack: vec![false; n],
+        stack: Vec::new(),
+        next: 0,
+        components: Vec::new(),
+    };
+    for v in 0..n {
+        if state.index[v].is_none() {
+            visit(&mut state, v);
+        }
+    }
+    state.components
+}
+
+/// Where every package goes in the rebuild
+pub struct Order {
+    /// commands of a stage run after those of the previous stage
+    pub stage: Vec<u32>,
+    /// tier of every package, packages of a cycle share the tier of the slowest one
+    pub tier: Vec<usize>,
+    /// packages depending on each other, rebuilt by one command
+    pub cycles: Vec<Vec<usize>>,
+    /// packages of a cycle have the same group
+    pub group: Vec<usize>,
+}
+
+/// Stages such that every package is rebuilt after its dependencies. The tiers of a stage run
+/// in order, so a dependency may be in an earlier tier of the same stage, or in the same tier
+/// if `same_command` is true, since emerge orders the packages of one command itself.
+pub fn order(deps: &[Vec<usize>], tier: &[usize], same_command: bool) -> Order {
+    let n = deps.len();
+    let mut order = Order {
+        stage: vec![0; n],
+        tier: tier.to_vec(),
+        cycles: Vec::new(),
+        group: vec![0; n],
+    };
+    for (group, component) in components(deps).into_iter().enumerate() {
+        let tier = component.iter().map(|&i| tier[i]).max().unwrap();
+        let mut stage = 0;
+        for &i in &component {
+            for &d in deps[i].iter().filter(|d| !component.contains(d)) {
+                let after = if order.tier[d] < tier || (same_command && order.tier[d] == tier) {
+                    order.stage[d]
+                } else {
+                    order.stage[d] + 1
+                };
+                stage = stage.max(after);
+            }
+        }
+        for &i in &component {
+            order.stage[i] = stage;
+            order.tier[i] = tier;
+            order.group[i] = group;
+        }
+        if component.len() > 1 {
+            order.cycles.push(component);
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn through_other_packages() {
+        let atoms = ["app-misc/a", "sys-libs/libxcrypt", "app-misc/b"];
+        let index: HashMap<_, _> = atoms.iter().enumerate().map(|(i, &a)| (a, i)).collect();
+        let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        // a -> virtual/libcrypt -> libxcrypt, b -> b-deps -> virtual/libcrypt, which is a cycle
+        let deps = [
+            strings(&["virtual/libcrypt", "dev-libs/unrelated"]),
+            strings(&[]),
+            strings(&["app-misc/b-deps"]),
+        ];
+        let installed = HashMap::from([
+            (
+                "virtual/libcrypt".to_owned(),
+                strings(&["sys-libs/libxcrypt"]),
+            ),
+            (
+                "app-misc/b-deps".to_owned(),
+                strings(&["virtual/libcrypt", "app-misc/b-deps"]),
+            ),
+        ]);
+        let deps = between(&deps, &index, &installed);
+        assert_eq!(deps, [vec![1], vec![], vec![1]]);
+    }
+
+    #[test]
+    fn cycle() {
+        // 0 and 1 depend on each other, 2 depends on 1
+        let deps = [vec![1], vec![0], vec![1]];
+        let order = order(&deps, &[0, 2, 1], true);
+        assert_eq!(order.cycles, [vec![0, 1]]);
+        assert_eq!(order.group[0], order.group[1]);
+        assert_ne!(order.group[0], order.group[2]);
+        // the cycle takes the tier of its slowest package
+        assert_eq!(order.tier, [2, 2, 1]);
+        assert_eq!(order.stage, [0, 0, 1]);
+    }
+
+    #[test]
+    fn diamond() {
+        // 3 depends on 1 and 2, which depend on 0
+        let deps = [vec![], vec![0], vec![0], vec![1, 2]];
+        let order = order(&deps, &[0; 4], false);
+        assert!(order.cycles.is_empty());
+        assert_eq!(order.stage, [0, 1, 1, 2]);
+        // emerge orders the packages of one command itself
+        let order = super::order(&deps, &[0; 4], true);
+        assert_eq!(order.stage, [0; 4]);
+    }
+
+    #[test]
+    fn disconnected() {
+        // 0 <- 1 and 2 <- 3 <- 4
+        let deps = [vec![], vec![0], vec![], vec![2], vec![3]];
+        let order = order(&deps, &[0; 5], false);
+        assert_eq!(order.stage, [0, 1, 0, 1, 2]);
+        // a dependency in an earlier tier doesn't need another stage
+        let order = super::order(&deps, &[0, 1, 0, 1, 0], false);
+        assert_eq!(order.stage, [0, 0, 0, 0, 1]);
+    }
+}
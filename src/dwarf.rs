@@ -0,0 +1,375 @@
+//! Just enough DWARF to read attributes of compile unit DIEs, without loading all of .debug_info
+
+use crate::elf::{c_str, Elf, Endian, ReadAt, SectionHeader};
+use std::{collections::BTreeSet, io::Result};
+
+const DW_AT_PRODUCER: u64 = 0x25;
+const DW_AT_STR_OFFSETS_BASE: u64 = 0x72;
+
+/// The compile unit DIE comes right after the unit header, its attributes are small.
+const CU_HEAD_SIZE: u64 = 1024;
+const MAX_STR_SIZE: u64 = 4096;
+
+struct Reader<'a> {
+    data: &'a [u8],
+    endian: Endian,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if n > self.data.len() {
+            return None;
+        }
+        let (head, rest) = self.data.split_at(n);
+        self.data = rest;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(self.endian.u16(self.bytes(2)?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(self.endian.u32(self.bytes(4)?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(self.endian.u64(self.bytes(8)?))
+    }
+
+    fn uint(&mut self, size: u8) -> Option<u64> {
+        match size {
+            1 => self.u8().map(u64::from),
+            2 => self.u16().map(u64::from),
+            3 => {
+                let b = self.bytes(3)?;
+                Some(match self.endian {
+                    Endian::Little => u32::from_le_bytes([b[0], b[1], b[2], 0]),
+                    Endian::Big => u32::from_be_bytes([0, b[0], b[1], b[2]]),
+                } as u64)
+            }
+            4 => self.u32().map(u64::from),
+            8 => self.u64(),
+            _ => None,
+        }
+    }
+
+    fn uleb(&mut self) -> Option<u64> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let b = self.u8()?;
+            if shift < 64 {
+                result |= ((b & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if b & 0x80 == 0 {
+                return Some(result);
+            }
+        }
+    }
+
+    fn skip_leb(&mut self) -> Option<()> {
+        while self.u8()? & 0x80 != 0 {}
+        Some(())
+    }
+
+    fn cstr(&mut self) -> Option<&'a [u8]> {
+        let len = self.data.iter().position(|&b| b == 0)?;
+        let s = self.bytes(len)?;
+        self.bytes(1)?;
+        Some(s)
+    }
+}
+
+/// Attribute values we care about, everything else is skipped
+enum Value<'a> {
+    Str(&'a [u8]),
+    /// offset into .debug_str
+    Strp(u64),
+    /// offset into .debug_line_str
+    LineStrp(u64),
+    /// index into .debug_str_offsets
+    Strx(u64),
+    Uint(u64),
+    Other,
+}
+
+#[derive(Clone, Copy)]
+struct Unit {
+    version: u16,
+    addr_size: u8,
+    offset_size: u8,
+}
+
+fn read_form<'a>(r: &mut Reader<'a>, form: u64, unit: Unit) -> Option<Value<'a>> {
+    let offset_size = unit.offset_size;
+    Some(match form {
+        // addr
+        0x01 => {
+            r.uint(unit.addr_size)?;
+            Value::Other
+        }
+        // block2, block4, block, block1, exprloc
+        0x03 | 0x04 | 0x09 | 0x0a | 0x18 => {
+            let len = match form {
+                0x03 => r.u16()? as u64,
+                0x04 => r.u32()? as u64,
+                0x0a => r.u8()? as u64,
+                _ => r.uleb()?,
+            };
+            r.bytes(usize::try_from(len).ok()?)?;
+            Value::Other
+        }
+        // data2, data4, data8, data1, flag, ref1, ref2, ref4, ref8, ref_sup4, ref_sig8, ref_sup8
+        0x05 => Value::Uint(r.uint(2)?),
+        0x06 => Value::Uint(r.uint(4)?),
+        0x07 => Value::Uint(r.uint(8)?),
+        0x0b | 0x0c => Value::Uint(r.uint(1)?),
+        0x11 => Value::Uint(r.uint(1)?),
+        0x12 => Value::Uint(r.uint(2)?),
+        0x13 | 0x1c => Value::Uint(r.uint(4)?),
+        0x14 | 0x20 | 0x24 => Value::Uint(r.uint(8)?),
+        0x1e => {
+            r.bytes(16)?;
+            Value::Other
+        }
+        0x08 => Value::Str(r.cstr()?),
+        0x0e => Value::Strp(r.uint(offset_size)?),
+        0x1f => Value::LineStrp(r.uint(offset_size)?),
+        // sec_offset, strp_sup, GNU_ref_alt, GNU_strp_alt
+        0x17 | 0x1d | 0x1f20 | 0x1f21 => Value::Uint(r.uint(offset_size)?),
+        // ref_addr is address sized in DWARF 2
+        0x10 if unit.version == 2 => Value::Uint(r.uint(unit.addr_size)?),
+        0x10 => Value::Uint(r.uint(offset_size)?),
+        // sdata
+        0x0d => {
+            r.skip_leb()?;
+            Value::Other
+        }
+        // udata, ref_udata, addrx, loclistx, rnglistx, GNU_addr_index
+        0x0f | 0x15 | 0x1b | 0x22 | 0x23 | 0x1f01 => Value::Uint(r.uleb()?),
+        // strx, GNU_str_index
+        0x1a | 0x1f02 => Value::Strx(r.uleb()?),
+        0x25 => Value::Strx(r.uint(1)?),
+        0x26 => Value::Strx(r.uint(2)?),
+        0x27 => Value::Strx(r.uint(3)?),
+        0x28 => Value::Strx(r.uint(4)?),
+        // addrx1-4
+        0x29..=0x2c => {
+            r.uint((form - 0x28) as u8)?;
+            Value::Other
+        }
+        // flag_present, implicit_const
+        0x19 | 0x21 => Value::Other,
+        // indirect
+        0x16 => {
+            let form = r.uleb()?;
+            return read_form(r, form, unit);
+        }
+        _ => return None,
+    })
+}
+
+/// `(attribute, form)` list of abbreviation `code` in the table at `offset`
+fn find_abbrev(abbrev: &[u8], offset: u64, code: u64, endian: Endian) -> Option<Vec<(u64, u64)>> {
+    let mut r = Reader {
+        data: abbrev.get(usize::try_from(offset).ok()?..)?,
+        endian,
+    };
+    loop {
+        let c = r.uleb()?;
+        if c == 0 {
+            return None;
+        }
+        let _tag = r.uleb()?;
+        let _children = r.u8()?;
+        let mut attrs = Vec::new();
+        loop {
+            let attr = r.uleb()?;
+            let form = r.uleb()?;
+            if attr == 0 && form == 0 {
+                break;
+            }
+            if form == 0x21 {
+                r.skip_leb()?;
+            }
+            attrs.push((attr, form));
+        }
+        if c == code {
+            return Some(attrs);
+        }
+    }
+}
+
+/// Walks the compile units of an ELF file's DWARF
+struct Dwarf<'a, R> {
+    elf: &'a Elf<R>,
+    sections: Vec<SectionHeader>,
+}
+
+impl<R: ReadAt> Dwarf<'_, R> {
+    fn section(&self, name: &str) -> Option<&SectionHeader> {
+        self.sections
+            .iter()
+            .find(|s| s.name == name && s.has_data())
+    }
+
+    /// Read at most `len` bytes at `offset` of a section
+    fn read(&self, section: &SectionHeader, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let len = len.min(section.size.saturating_sub(offset));
+        self.elf.read(section.offset + offset, len)
+    }
+
+    fn string(&self, section: &str, offset: u64) -> Result<Option<String>> {
+        match self.section(section) {
+            Some(s) if offset < s.size => Ok(Some(c_str(&self.read(s, offset, MAX_STR_SIZE)?, 0))),
+            _ => Ok(None),
+        }
+    }
+
+    fn resolve(
+        &self,
+        value: &Value,
+        unit: Unit,
+        str_offsets_base: Option<u64>,
+    ) -> Result<Option<String>> {
+        match *value {
+            Value::Str(s) => Ok(Some(String::from_utf8_lossy(s).into_owned())),
+            Value::Strp(offset) => self.string(".debug_str", offset),
+            Value::LineStrp(offset) => self.string(".debug_line_str", offset),
+            Value::Strx(index) => {
+                let Some(offsets) = self.section(".debug_str_offsets") else {
+                    return Ok(None);
+                };
+                // the offsets table has an 8 byte header (16 for 64-bit DWARF)
+                let size = unit.offset_size as u64;
+                let base = str_offsets_base.unwrap_or(2 * size);
+                // `index` comes from the file, don't trust it to stay in range
+                let Some(offset) = index
+                    .checked_mul(size)
+                    .and_then(|o| o.checked_add(base))
+                    .filter(|&o| o < offsets.size)
+                else {
+                    return Ok(None);
+                };
+                let entry = self.read(offsets, offset, size)?;
+                let mut r = Reader {
+                    data: &entry,
+                    endian: self.elf.endian,
+                };
+                match r.uint(unit.offset_size) {
+                    Some(offset) => self.string(".debug_str", offset),
+                    None => Ok(None),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// `DW_AT_producer` of the compile unit at `offset`, and the offset of the next unit
+    fn producer(
+        &self,
+        abbrev: &[u8],
+        info: &SectionHeader,
+        offset: u64,
+    ) -> Result<(Option<String>, Option<u64>)> {
+        let head = self.read(info, offset, CU_HEAD_SIZE)?;
+        let mut r = Reader {
+            data: &head,
+            endian: self.elf.endian,
+        };
+        let Some(len) = r.u32() else {
+            return Ok((None, None));
+        };
+        let (offset_size, len, next) = match len {
+            0xffff_ffff => match r.u64() {
+                Some(len) => (
+                    8,
+                    len,
+                    offset.checked_add(12).and_then(|o| o.checked_add(len)),
+                ),
+                None => return Ok((None, None)),
+            },
+            0xffff_fff0.. => return Ok((None, None)),
+            len => (4, len as u64, Some(offset + 4 + len as u64)),
+        };
+        if len == 0 {
+            return Ok((None, next));
+        }
+        let parsed = (|| {
+            let version = r.u16()?;
+            let (abbrev_offset, addr_size) = match version {
+                2..=4 => (r.uint(offset_size)?, r.u8()?),
+                5 => {
+                    let unit_type = r.u8()?;
+                    let addr_size = r.u8()?;
+                    let abbrev_offset = r.uint(offset_size)?;
+                    match unit_type {
+                        // type units: signature and type offset
+                        0x02 | 0x06 => {
+                            r.bytes(8 + offset_size as usize)?;
+                        }
+                        // skeleton and split units: dwo id
+                        0x04 | 0x05 => {
+                            r.bytes(8)?;
+                        }
+                        _ => {}
+                    }
+                    (abbrev_offset, addr_size)
+                }
+                _ => return None,
+            };
+            let unit = Unit {
+                version,
+                addr_size,
+                offset_size,
+            };
+            let code = r.uleb()?;
+            let attrs = find_abbrev(abbrev, abbrev_offset, code, self.elf.endian)?;
+            let mut producer = None;
+            let mut str_offsets_base = None;
+            for (attr, form) in attrs {
+                let value = read_form(&mut r, form, unit)?;
+                match (attr, value) {
+                    (DW_AT_PRODUCER, value) => producer = Some(value),
+                    (DW_AT_STR_OFFSETS_BASE, Value::Uint(base)) => str_offsets_base = Some(base),
+                    _ => {}
+                }
+            }
+            Some((producer?, unit, str_offsets_base))
+        })();
+        match parsed {
+            Some((producer, unit, base)) => Ok((self.resolve(&producer, unit, base)?, next)),
+            None => Ok((None, next)),
+        }
+    }
+}
+
+/// `DW_AT_producer` of every compile unit, empty if there is no (uncompressed) DWARF
+pub fn producers<R: ReadAt>(elf: &Elf<R>) -> Result<BTreeSet<String>> {
+    let dwarf = Dwarf {
+        elf,
+        sections: elf.sections()?,
+    };
+    let mut producers = BTreeSet::new();
+    let (Some(info), Some(abbrev)) = (dwarf.section(".debug_info"), dwarf.section(".debug_abbrev"))
+    else {
+        return Ok(producers);
+    };
+    let abbrev = elf.read(abbrev.offset, abbrev.size)?;
+    let mut offset = 0;
+    while offset < info.size {
+        let (producer, next) = dwarf.producer(&abbrev, info, offset)?;
+        producers.extend(producer);
+        match next {
+            Some(next) if next > offset => offset = next,
+            _ => break,
+        }
+    }
+    Ok(producers)
+}
@@ -0,0 +1,643 @@
+use serde::Serialize;
+use std::{
+    fmt,
+    fs::File,
+    io::{Error, ErrorKind, Result},
+    os::unix::fs::FileExt,
+};
+
+pub const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+pub const PT_GNU_STACK: u32 = 0x6474_e551;
+pub const PT_GNU_RELRO: u32 = 0x6474_e552;
+const PT_GNU_PROPERTY: u32 = 0x6474_e553;
+pub const PF_X: u32 = 1;
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+const SHT_DYNSYM: u32 = 11;
+const SHF_COMPRESSED: u64 = 0x800;
+
+const NT_GNU_PROPERTY_TYPE_0: u32 = 5;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_STRSZ: u64 = 10;
+const DT_SONAME: u64 = 14;
+const DT_RPATH: u64 = 15;
+const DT_RUNPATH: u64 = 29;
+pub const DT_BIND_NOW: u64 = 24;
+pub const DT_FLAGS: u64 = 30;
+pub const DT_FLAGS_1: u64 = 0x6fff_fffb;
+pub const DF_BIND_NOW: u64 = 0x8;
+pub const DF_1_NOW: u64 = 0x1;
+const DF_1_PIE: u64 = 0x0800_0000;
+
+/// Something ELF can be read from: a file, or an in-memory buffer
+pub trait ReadAt {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<()>;
+    fn size(&self) -> Result<u64>;
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<()> {
+        (**self).read_exact_at(buf, offset)
+    }
+
+    fn size(&self) -> Result<u64> {
+        (**self).size()
+    }
+}
+
+impl ReadAt for File {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<()> {
+        FileExt::read_exact_at(self, buf, offset)
+    }
+
+    fn size(&self) -> Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+impl ReadAt for [u8] {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> Result<()> {
+        let data = usize::try_from(offset)
+            .ok()
+            .and_then(|start| self.get(start..start.checked_add(buf.len())?))
+            .ok_or_else(|| Error::from(ErrorKind::UnexpectedEof))?;
+        buf.copy_from_slice(data);
+        Ok(())
+    }
+
+    fn size(&self) -> Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Class {
+    Elf32,
+    Elf64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    pub fn u16(self, b: &[u8]) -> u16 {
+        let b = b[..2].try_into().unwrap();
+        match self {
+            Endian::Little => u16::from_le_bytes(b),
+            Endian::Big => u16::from_be_bytes(b),
+        }
+    }
+
+    pub fn u32(self, b: &[u8]) -> u32 {
+        let b = b[..4].try_into().unwrap();
+        match self {
+            Endian::Little => u32::from_le_bytes(b),
+            Endian::Big => u32::from_be_bytes(b),
+        }
+    }
+
+    pub fn u64(self, b: &[u8]) -> u64 {
+        let b = b[..8].try_into().unwrap();
+        match self {
+            Endian::Little => u64::from_le_bytes(b),
+            Endian::Big => u64::from_be_bytes(b),
+        }
+    }
+}
+
+/// `e_type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Type {
+    Rel,
+    Exec,
+    Dyn,
+    Core,
+    Other(u16),
+}
+
+impl From<u16> for Type {
+    fn from(t: u16) -> Self {
+        match t {
+            1 => Type::Rel,
+            2 => Type::Exec,
+            3 => Type::Dyn,
+            4 => Type::Core,
+            t => Type::Other(t),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProgramHeader {
+    pub p_type: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub filesz: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SectionHeader {
+    pub name: String,
+    pub sh_type: u32,
+    pub flags: u64,
+    pub offset: u64,
+    pub size: u64,
+    /// index of the associated section, e.g. the string table of a symbol table
+    pub link: u32,
+}
+
+impl SectionHeader {
+    /// Not `SHT_NOBITS` and not compressed, i.e. the contents can be read from the file as is
+    pub fn has_data(&self) -> bool {
+        self.sh_type != SHT_NOBITS && self.flags & SHF_COMPRESSED == 0
+    }
+}
+
+/// Parsed ELF header and program headers, the rest is read lazily
+#[derive(Debug)]
+pub struct Elf<R> {
+    reader: R,
+    size: u64,
+    pub class: Class,
+    pub endian: Endian,
+    pub osabi: u8,
+    pub e_type: Type,
+    pub machine: u16,
+    pub phdrs: Vec<ProgramHeader>,
+    shoff: u64,
+    shentsize: u64,
+    shnum: u64,
+    shstrndx: u64,
+}
+
+/// String entries of the dynamic section
+#[derive(Debug, Clone, Default)]
+pub struct DynamicStrings {
+    pub soname: Option<String>,
+    pub needed: Vec<String>,
+    pub rpath: Vec<String>,
+    pub runpath: Vec<String>,
+}
+
+/// Everything we want to know about an ELF file
+#[derive(Debug, Clone, Serialize)]
+pub struct ElfInfo {
+    pub class: Class,
+    pub endian: Endian,
+    pub osabi: u8,
+    #[serde(rename = "type")]
+    pub e_type: Type,
+    pub machine: u16,
+    /// `PT_INTERP`
+    pub interp: Option<String>,
+    pub pie: bool,
+}
+
+/// `e_ident` at the start of every ELF file
+pub const IDENT_SIZE: usize = 16;
+
+fn parse_ident(ident: &[u8; IDENT_SIZE]) -> Result<(Class, Endian)> {
+    if ident[..4] != ELF_MAGIC {
+        return Err(invalid("bad ELF magic"));
+    }
+    let class = match ident[4] {
+        1 => Class::Elf32,
+        2 => Class::Elf64,
+        _ => return Err(invalid("bad ELF class")),
+    };
+    let endian = match ident[5] {
+        1 => Endian::Little,
+        2 => Endian::Big,
+        _ => return Err(invalid("bad ELF data encoding")),
+    };
+    if ident[6] != 1 {
+        return Err(invalid("bad ELF version"));
+    }
+    Ok((class, endian))
+}
+
+/// Whether `head` starts with a valid `e_ident`, for files which can't be parsed as a whole
+pub fn is_elf_ident(head: &[u8]) -> bool {
+    head.first_chunk()
+        .is_some_and(|ident| parse_ident(ident).is_ok())
+}
+
+impl<R: ReadAt> Elf<R> {
+    /// Fails with `InvalidData` or `UnexpectedEof` if this isn't a well-formed ELF file
+    pub fn parse(reader: R) -> Result<Self> {
+        let size = reader.size()?;
+        let mut ident = [0u8; IDENT_SIZE];
+        reader.read_exact_at(&mut ident, 0)?;
+        let (class, endian) = parse_ident(&ident)?;
+        let ehsize = match class {
+            Class::Elf32 => 52,
+            Class::Elf64 => 64,
+        };
+        let mut ehdr = vec![0u8; ehsize];
+        reader.read_exact_at(&mut ehdr, 0)?;
+        let mut elf = Elf {
+            reader,
+            size,
+            class,
+            endian,
+            osabi: ident[7],
+            e_type: endian.u16(&ehdr[16..]).into(),
+            machine: endian.u16(&ehdr[18..]),
+            phdrs: Vec::new(),
+            shoff: 0,
+            shentsize: 0,
+            shnum: 0,
+            shstrndx: 0,
+        };
+        let (phoff, shoff, rest) = match class {
+            Class::Elf32 => (
+                endian.u32(&ehdr[28..]) as u64,
+                endian.u32(&ehdr[32..]) as u64,
+                &ehdr[40..],
+            ),
+            Class::Elf64 => (
+                endian.u64(&ehdr[32..]),
+                endian.u64(&ehdr[40..]),
+                &ehdr[52..],
+            ),
+        };
+        elf.shoff = shoff;
+        elf.shentsize = endian.u16(&rest[6..]) as u64;
+        elf.shnum = endian.u16(&rest[8..]) as u64;
+        elf.shstrndx = endian.u16(&rest[10..]) as u64;
+        if endian.u16(rest) as usize != ehsize {
+            return Err(invalid("bad ELF header size"));
+        }
+        let phentsize = endian.u16(&rest[2..]) as u64;
+        let phnum = endian.u16(&rest[4..]) as u64;
+        if phnum != 0 {
+            let expect = match class {
+                Class::Elf32 => 32,
+                Class::Elf64 => 56,
+            };
+            if phentsize != expect {
+                return Err(invalid("bad program header size"));
+            }
+            let phdrs = elf.read(phoff, phentsize * phnum)?;
+            elf.phdrs = phdrs
+                .chunks_exact(phentsize as usize)
+                .map(|p| elf.program_header(p))
+                .collect();
+        }
+        Ok(elf)
+    }
+
+    fn program_header(&self, p: &[u8]) -> ProgramHeader {
+        let e = self.endian;
+        match self.class {
+            Class::Elf32 => ProgramHeader {
+                p_type: e.u32(p),
+                flags: e.u32(&p[24..]),
+                offset: e.u32(&p[4..]) as u64,
+                vaddr: e.u32(&p[8..]) as u64,
+                filesz: e.u32(&p[16..]) as u64,
+            },
+            Class::Elf64 => ProgramHeader {
+                p_type: e.u32(p),
+                flags: e.u32(&p[4..]),
+                offset: e.u64(&p[8..]),
+                vaddr: e.u64(&p[16..]),
+                filesz: e.u64(&p[32..]),
+            },
+        }
+    }
+
+    /// Section headers with their names, empty if the file has none
+    pub fn sections(&self) -> Result<Vec<SectionHeader>> {
+        if self.shoff == 0 || self.shnum == 0 {
+            return Ok(Vec::new());
+        }
+        let expect = match self.class {
+            Class::Elf32 => 40,
+            Class::Elf64 => 64,
+        };
+        if self.shentsize != expect || self.shstrndx >= self.shnum {
+            return Err(invalid("bad section header"));
+        }
+        let shdrs = self.read(self.shoff, self.shentsize * self.shnum)?;
+        let shdrs: Vec<_> = shdrs
+            .chunks_exact(self.shentsize as usize)
+            .map(|s| self.section_header(s))
+            .collect();
+        let shstrtab = &shdrs[self.shstrndx as usize].1;
+        let shstrtab = self.read(shstrtab.offset, shstrtab.size)?;
+        Ok(shdrs
+            .into_iter()
+            .map(|(name, mut shdr)| {
+                shdr.name = c_str(&shstrtab, name as usize);
+                shdr
+            })
+            .collect())
+    }
+
+    fn section_header(&self, s: &[u8]) -> (u32, SectionHeader) {
+        let e = self.endian;
+        let shdr = match self.class {
+            Class::Elf32 => SectionHeader {
+                name: String::new(),
+                sh_type: e.u32(&s[4..]),
+                flags: e.u32(&s[8..]) as u64,
+                offset: e.u32(&s[16..]) as u64,
+                size: e.u32(&s[20..]) as u64,
+                link: e.u32(&s[24..]),
+            },
+            Class::Elf64 => SectionHeader {
+                name: String::new(),
+                sh_type: e.u32(&s[4..]),
+                flags: e.u64(&s[8..]),
+                offset: e.u64(&s[24..]),
+                size: e.u64(&s[32..]),
+                link: e.u32(&s[40..]),
+            },
+        };
+        (e.u32(s), shdr)
+    }
+
+    /// Contents of the section named `name`, `None` if there is no such section
+    /// or it has no data in the file. Compressed sections are treated as missing.
+    pub fn section_data(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let sections = self.sections()?;
+        match sections.iter().find(|s| s.name == name) {
+            Some(s) if s.has_data() => self.read(s.offset, s.size).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Names of all symbols in `.symtab` and `.dynsym`
+    pub fn symbols(&self) -> Result<Vec<String>> {
+        let sections = self.sections()?;
+        let entsize = match self.class {
+            Class::Elf32 => 16,
+            Class::Elf64 => 24,
+        };
+        let mut symbols = Vec::new();
+        for symtab in sections
+            .iter()
+            .filter(|s| matches!(s.sh_type, SHT_SYMTAB | SHT_DYNSYM) && s.has_data())
+        {
+            let Some(strtab) = sections.get(symtab.link as usize).filter(|s| s.has_data()) else {
+                continue;
+            };
+            let strtab = self.read(strtab.offset, strtab.size)?;
+            let data = self.read(symtab.offset, symtab.size)?;
+            symbols.extend(
+                data.chunks_exact(entsize)
+                    .map(|sym| self.endian.u32(sym))
+                    .filter(|&name| name != 0)
+                    .map(|name| c_str(&strtab, name as usize)),
+            );
+        }
+        Ok(symbols)
+    }
+
+    /// Read `len` bytes at `offset`, bounded by file size so garbage offsets can't allocate much
+    pub fn read(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.size => {
+                let mut buf = vec![0u8; len as usize];
+                self.reader.read_exact_at(&mut buf, offset)?;
+                Ok(buf)
+            }
+            _ => Err(Error::from(ErrorKind::UnexpectedEof)),
+        }
+    }
+
+    fn segment(&self, p_type: u32) -> Result<Option<Vec<u8>>> {
+        match self.phdrs.iter().find(|p| p.p_type == p_type) {
+            Some(p) => self.read(p.offset, p.filesz).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// `(pr_type, data)` of the properties in `.note.gnu.property`
+    pub fn gnu_properties(&self) -> Result<Vec<(u32, Vec<u8>)>> {
+        let notes = match self.section_data(".note.gnu.property")? {
+            Some(notes) => notes,
+            None => match self.segment(PT_GNU_PROPERTY)? {
+                Some(notes) => notes,
+                None => return Ok(Vec::new()),
+            },
+        };
+        // unlike other notes, property notes are 8 byte aligned in ELF64
+        let align = match self.class {
+            Class::Elf32 => 4,
+            Class::Elf64 => 8,
+        };
+        let pad = |n: usize| n.div_ceil(align) * align;
+        let e = self.endian;
+        let mut properties = Vec::new();
+        let mut notes = &notes[..];
+        while notes.len() >= 12 {
+            let namesz = e.u32(notes) as usize;
+            let descsz = e.u32(&notes[4..]) as usize;
+            let n_type = e.u32(&notes[8..]);
+            let desc_start = pad(12 + namesz);
+            let Some(desc) = notes.get(desc_start..desc_start + descsz) else {
+                break;
+            };
+            if n_type == NT_GNU_PROPERTY_TYPE_0 && notes[12..12 + namesz] == *b"GNU\0" {
+                let mut desc = desc;
+                while desc.len() >= 8 {
+                    let pr_type = e.u32(desc);
+                    let datasz = e.u32(&desc[4..]) as usize;
+                    let Some(data) = desc.get(8..8 + datasz) else {
+                        break;
+                    };
+                    properties.push((pr_type, data.to_vec()));
+                    desc = desc.get(8 + pad(datasz)..).unwrap_or_default();
+                }
+            }
+            notes = notes.get(desc_start + pad(descsz)..).unwrap_or_default();
+        }
+        Ok(properties)
+    }
+
+    pub fn interp(&self) -> Result<Option<String>> {
+        Ok(self.segment(PT_INTERP)?.map(|s| c_str(&s, 0)))
+    }
+
+    /// `(d_tag, d_val)` of the dynamic section, up to `DT_NULL`
+    pub fn dynamic(&self) -> Result<Vec<(u64, u64)>> {
+        let Some(dynamic) = self.segment(PT_DYNAMIC)? else {
+            return Ok(Vec::new());
+        };
+        let e = self.endian;
+        let entries = match self.class {
+            Class::Elf32 => dynamic
+                .chunks_exact(8)
+                .map(|d| (e.u32(d) as u64, e.u32(&d[4..]) as u64))
+                .collect::<Vec<_>>(),
+            Class::Elf64 => dynamic
+                .chunks_exact(16)
+                .map(|d| (e.u64(d), e.u64(&d[8..])))
+                .collect(),
+        };
+        Ok(entries
+            .into_iter()
+            .take_while(|&(tag, _)| tag != DT_NULL)
+            .collect())
+    }
+
+    /// File offset of a virtual address, using the `PT_LOAD` segments, `None` if the segment's
+    /// offset is garbage
+    fn vaddr_offset(&self, addr: u64) -> Option<u64> {
+        self.phdrs
+            .iter()
+            .find(|p| p.p_type == PT_LOAD && p.vaddr <= addr && addr - p.vaddr < p.filesz)
+            .and_then(|p| (addr - p.vaddr).checked_add(p.offset))
+    }
+
+    /// Soname, DT_NEEDED, RPATH and RUNPATH from the dynamic string table
+    pub fn dynamic_strings(&self) -> Result<DynamicStrings> {
+        let dynamic = self.dynamic()?;
+        let find = |tag| dynamic.iter().find(|d| d.0 == tag).map(|d| d.1);
+        let mut strings = DynamicStrings::default();
+        let (Some(strtab), Some(strsz)) = (find(DT_STRTAB), find(DT_STRSZ)) else {
+            return Ok(strings);
+        };
+        let Some(offset) = self.vaddr_offset(strtab) else {
+            return Err(invalid("DT_STRTAB outside of loaded segments"));
+        };
+        let strtab = self.read(offset, strsz)?;
+        let paths = |s: String| -> Vec<String> {
+            s.split(':')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_owned())
+                .collect()
+        };
+        for &(tag, val) in &dynamic {
+            let s = || c_str(&strtab, val as usize);
+            match tag {
+                DT_SONAME => strings.soname = Some(s()),
+                DT_NEEDED => strings.needed.push(s()),
+                DT_RPATH => strings.rpath.extend(paths(s())),
+                DT_RUNPATH => strings.runpath.extend(paths(s())),
+                _ => {}
+            }
+        }
+        Ok(strings)
+    }
+
+    /// `ET_DYN` with `DF_1_PIE`, or, for old linkers which don't set it,
+    /// `ET_DYN` with an interpreter but without a soname (libc.so.6 has an interpreter too)
+    pub fn is_pie(&self) -> Result<bool> {
+        if self.e_type != Type::Dyn {
+            return Ok(false);
+        }
+        let dynamic = self.dynamic()?;
+        let flags_1 = dynamic
+            .iter()
+            .find(|&&(tag, _)| tag == DT_FLAGS_1)
+            .map_or(0, |&(_, val)| val);
+        if flags_1 & DF_1_PIE != 0 {
+            return Ok(true);
+        }
+        let has_soname = dynamic.iter().any(|&(tag, _)| tag == DT_SONAME);
+        Ok(!has_soname && self.interp()?.is_some())
+    }
+
+    pub fn info(&self) -> Result<ElfInfo> {
+        Ok(ElfInfo {
+            class: self.class,
+            endian: self.endian,
+            osabi: self.osabi,
+            e_type: self.e_type,
+            machine: self.machine,
+            interp: self.interp()?,
+            pie: self.is_pie()?,
+        })
+    }
+}
+
+/// NUL terminated string at `offset` of a string table
+pub fn c_str(table: &[u8], offset: usize) -> String {
+    let s = table.get(offset..).unwrap_or_default();
+    let s = s.split(|&b| b == 0).next().unwrap_or_default();
+    String::from_utf8_lossy(s).into_owned()
+}
+
+/// Name of `e_machine`, as `file(1)` and binutils spell it
+pub fn machine_name(machine: u16) -> Option<&'static str> {
+    Some(match machine {
+        2 => "SPARC",
+        3 => "x86",
+        4 => "m68k",
+        8 => "MIPS",
+        15 => "PA-RISC",
+        20 => "PowerPC",
+        21 => "PowerPC64",
+        22 => "S/390",
+        40 => "ARM",
+        42 => "SuperH",
+        43 => "SPARC V9",
+        50 => "IA-64",
+        62 => "x86-64",
+        183 => "AArch64",
+        190 => "CUDA",
+        224 => "AMDGPU",
+        243 => "RISC-V",
+        247 => "BPF",
+        258 => "LoongArch",
+        0x9026 => "Alpha",
+        _ => return None,
+    })
+}
+
+impl fmt::Display for ElfInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let class = match self.class {
+            Class::Elf32 => "ELF32",
+            Class::Elf64 => "ELF64",
+        };
+        let endian = match self.endian {
+            Endian::Little => "LSB",
+            Endian::Big => "MSB",
+        };
+        write!(f, "{class} {endian}")?;
+        match self.e_type {
+            Type::Rel => write!(f, " REL")?,
+            Type::Exec => write!(f, " EXEC")?,
+            Type::Dyn => write!(f, " DYN")?,
+            Type::Core => write!(f, " CORE")?,
+            Type::Other(t) => write!(f, " type {t:#x}")?,
+        }
+        match machine_name(self.machine) {
+            Some(name) => write!(f, " {name}")?,
+            None => write!(f, " machine {}", self.machine)?,
+        }
+        if self.osabi != 0 {
+            write!(f, " osabi {}", self.osabi)?;
+        }
+        if self.pie {
+            write!(f, " PIE")?;
+        }
+        if let Some(interp) = &self.interp {
+            write!(f, " interp {interp}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{root::Root, vdb::split_pf};
+use clap::ArgEnum;
+use color_eyre::eyre::Context;
+use serde::Serialize;
+use std::{collections::HashMap, fs, io::ErrorKind as ioErrorKind};
+
+const EMERGE_LOG_PATH: &str = "/var/log/emerge.log";
+
+/// Which of the build time statistics to use
+#[derive(Debug, Clone, Copy, ArgEnum)]
+pub enum Stat {
+    Average,
+    Median,
+    Min,
+    Max,
+    Last,
+}
+
+/// Build durations of one package in seconds
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct BuildTime {
+    pub average: u64,
+    pub median: u64,
+    pub min: u64,
+    pub max: u64,
+    /// duration of the most recent build
+    pub last: u64,
+}
+
+impl BuildTime {
+    fn new(durations: &[u64]) -> Self {
+        let mut sorted = durations.to_vec();
+        sorted.sort_unstable();
+        let count = sorted.len();
+        let median = if count.is_multiple_of(2) {
+            (sorted[count / 2 - 1] + sorted[count / 2]) / 2
+        } else {
+            sorted[count / 2]
+        };
+        BuildTime {
+            average: sorted.iter().sum::<u64>() / count as u64,
+            median,
+            min: sorted[0],
+            max: sorted[count - 1],
+            last: *durations.last().unwrap(),
+        }
+    }
+
+    pub fn get(&self, stat: Stat) -> u64 {
+        match stat {
+            Stat::Average => self.average,
+            Stat::Median => self.median,
+            Stat::Min => self.min,
+            Stat::Max => self.max,
+            Stat::Last => self.last,
+        }
+    }
+}
+
+/// `(1 of 3) cat/pf::repo to /` => `cat/pf`
+fn parse_cpv(s: &str) -> Option<&str> {
+    let (_, rest) = s.strip_prefix('(')?.split_once(") ")?;
+    let cpv = rest.split_whitespace().next()?;
+    Some(cpv.split_once("::").map_or(cpv, |(cpv, _repo)| cpv))
+}
+
+/// Parse emerge.log and return build time of every package keyed by CAT/PN.
+///
+/// A build starts at `>>> emerge (x of y) cat/pf` and ends at the next
+/// `::: completed emerge (x of y) cat/pf`, builds which never completed are ignored.
+pub fn build_times(root: &Root) -> HashMap<String, BuildTime> {
+    let path = root.prefixed(EMERGE_LOG_PATH);
+    let log = match fs::read(&path) {
+        Ok(log) => log,
+        Err(e) if e.kind() == ioErrorKind::NotFound => return HashMap::new(),
+        Err(e) => Err(e)
+            .with_context(|| format!("Failed to read file: {path}"))
+            .unwrap(),
+    };
+    parse(&String::from_utf8_lossy(&log))
+}
+
+/// Build time of every package of the lines of emerge.log, see `build_times`
+fn parse(log: &str) -> HashMap<String, BuildTime> {
+    let mut started: HashMap<&str, u64> = HashMap::new();
+    let mut durations: HashMap<String, Vec<u64>> = HashMap::new();
+    for line in log.lines() {
+        let Some((time, msg)) = line.split_once(':') else {
+            continue;
+        };
+        let Ok(time) = time.parse::<u64>() else {
+            continue;
+        };
+        let msg = msg.trim_start();
+        if let Some(cpv) = msg.strip_prefix(">>> emerge ").and_then(parse_cpv) {
+            started.insert(cpv, time);
+        } else if let Some(cpv) = msg
+            .strip_prefix("::: completed emerge ")
+            .and_then(parse_cpv)
+        {
+            let Some(start) = started.remove(cpv) else {
+                continue;
+            };
+            let Some((cat, pf)) = cpv.split_once('/') else {
+                continue;
+            };
+            let Some((pn, _)) = split_pf(pf) else {
+                continue;
+            };
+            durations
+                .entry(format!("{cat}/{pn}"))
+                .or_default()
+                .push(time.saturating_sub(start));
+        }
+    }
+    durations
+        .into_iter()
+        .map(|(atom, d)| (atom, BuildTime::new(&d)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completed_emerge() {
+        let log = "\
+100:  >>> emerge (1 of 2) sys-apps/foo-1.2-r1::gentoo to /
+160:  ::: completed emerge (1 of 2) sys-apps/foo-1.2-r1::gentoo to /
+200:  >>> emerge (2 of 2) sys-apps/foo-1.3 to /
+300:  ::: completed emerge (2 of 2) sys-apps/foo-1.3 to /
+";
+        let times = parse(log);
+        let foo = &times["sys-apps/foo"];
+        assert_eq!(
+            (foo.min, foo.max, foo.average, foo.last),
+            (60, 100, 80, 100)
+        );
+    }
+
+    #[test]
+    fn interrupted_emerge() {
+        // the first build never completed, the second one started over
+        let log = "\
+100:  >>> emerge (1 of 1) dev-libs/bar-2 to /
+500:  >>> emerge (1 of 1) dev-libs/bar-2 to /
+530:  ::: completed emerge (1 of 1) dev-libs/bar-2 to /
+600:  >>> emerge (1 of 1) dev-libs/baz-1 to /
+";
+        let times = parse(log);
+        assert_eq!(times["dev-libs/bar"].last, 30);
+        assert!(!times.contains_key("dev-libs/baz"));
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let log = "\
+garbage
+x:  >>> emerge (1 of 1) dev-libs/bar-2 to /
+100:  >>> emerge dev-libs/bar-2 to /
+110:  ::: completed emerge (1 of 1) dev-libs/bar-2 to /
+120:  >>> emerge (1 of 1) notanatom to /
+130:  ::: completed emerge (1 of 1) notanatom to /
+";
+        assert!(parse(log).is_empty());
+    }
+}
@@ -0,0 +1,86 @@
+//! Compiler and linker flags a package was built with, as recorded in the VDB
+
+use crate::vdb::Entry;
+use serde::Serialize;
+use std::{collections::HashMap, fmt};
+
+const VARS: [&str; 5] = ["CFLAGS", "CXXFLAGS", "LDFLAGS", "CHOST", "CBUILD"];
+
+/// `None` if a variable isn't recorded or isn't set
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BuildFlags {
+    pub cflags: Option<String>,
+    pub cxxflags: Option<String>,
+    pub ldflags: Option<String>,
+    pub chost: Option<String>,
+    pub cbuild: Option<String>,
+}
+
+/// Whitespace is normalized, so flags can be compared as strings
+fn normalize(flags: &str) -> String {
+    flags.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl BuildFlags {
+    fn from_fn(mut get: impl FnMut(&str) -> Option<String>) -> Self {
+        let [cflags, cxxflags, ldflags, chost, cbuild] =
+            VARS.map(|v| get(v).map(|f| normalize(&f)));
+        BuildFlags {
+            cflags,
+            cxxflags,
+            ldflags,
+            chost,
+            cbuild,
+        }
+    }
+
+    /// Flags portage recorded for a VDB entry
+    pub fn recorded(entry: &Entry) -> Self {
+        Self::from_fn(|var| entry.read(var))
+    }
+
+    /// Flags set in make.conf or another file of variables
+    pub fn from_vars(vars: &HashMap<String, String>) -> Self {
+        Self::from_fn(|var| vars.get(var).cloned())
+    }
+
+    fn vars(&self) -> [(&'static str, Option<&str>); 5] {
+        [
+            ("CFLAGS", self.cflags.as_deref()),
+            ("CXXFLAGS", self.cxxflags.as_deref()),
+            ("LDFLAGS", self.ldflags.as_deref()),
+            ("CHOST", self.chost.as_deref()),
+            ("CBUILD", self.cbuild.as_deref()),
+        ]
+    }
+
+    /// Variables set in both which differ, e.g. `CFLAGS`
+    pub fn differences(&self, other: &BuildFlags) -> Vec<&'static str> {
+        self.vars()
+            .into_iter()
+            .zip(other.vars())
+            .filter_map(|((var, a), (_, b))| (a.is_some() && b.is_some() && a != b).then_some(var))
+            .collect()
+    }
+
+    /// Whether CFLAGS, CXXFLAGS or LDFLAGS has `flag`, `-flto` is also found as `-flto=auto`
+    pub fn has(&self, flag: &str) -> bool {
+        [&self.cflags, &self.cxxflags, &self.ldflags]
+            .into_iter()
+            .flatten()
+            .flat_map(|flags| flags.split(' '))
+            .any(|f| f == flag || f.strip_prefix(flag).is_some_and(|r| r.starts_with('=')))
+    }
+}
+
+/// `CFLAGS="-O2 -pipe" LDFLAGS="-Wl,-O1"`, like make.conf
+impl fmt::Display for BuildFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let vars: Vec<_> = self
+            .vars()
+            .into_iter()
+            .filter_map(|(var, value)| Some(format!("{var}=\"{}\"", value?)))
+            .collect();
+        write!(f, "{}", vars.join(" "))
+    }
+}
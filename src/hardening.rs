@@ -0,0 +1,135 @@
+use crate::elf::{
+    Elf, ReadAt, Type, DF_1_NOW, DF_BIND_NOW, DT_BIND_NOW, DT_FLAGS, DT_FLAGS_1, PF_X,
+    PT_GNU_RELRO, PT_GNU_STACK,
+};
+use clap::ArgEnum;
+use serde::Serialize;
+use std::{fmt, io::Result};
+
+/// One checksec-style check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ArgEnum)]
+pub enum Check {
+    Pie,
+    Relro,
+    BindNow,
+    Canary,
+    Fortify,
+    Nx,
+}
+
+impl Check {
+    /// How a file failing this check is reported
+    pub fn failure(self) -> &'static str {
+        match self {
+            Check::Pie => "no PIE",
+            Check::Relro => "no full RELRO",
+            Check::BindNow => "no BIND_NOW",
+            Check::Canary => "no canary",
+            Check::Fortify => "no FORTIFY",
+            Check::Nx => "executable stack",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Relro {
+    None,
+    Partial,
+    Full,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Hardening {
+    /// `None` for shared libraries, which can't be PIE
+    pub pie: Option<bool>,
+    pub relro: Relro,
+    pub bind_now: bool,
+    /// references `__stack_chk_fail`
+    pub canary: bool,
+    /// references any `__*_chk` function
+    pub fortify: bool,
+    /// has a non executable `PT_GNU_STACK`
+    pub nx: bool,
+}
+
+impl Hardening {
+    pub fn passes(&self, check: Check) -> bool {
+        match check {
+            Check::Pie => self.pie != Some(false),
+            Check::Relro => self.relro == Relro::Full,
+            Check::BindNow => self.bind_now,
+            Check::Canary => self.canary,
+            Check::Fortify => self.fortify,
+            Check::Nx => self.nx,
+        }
+    }
+}
+
+/// Audit an executable or shared library, `None` for other ELF types
+pub fn check<R: ReadAt>(elf: &Elf<R>) -> Result<Option<Hardening>> {
+    let pie = match elf.e_type {
+        Type::Exec => Some(false),
+        Type::Dyn if elf.is_pie()? => Some(true),
+        Type::Dyn => None,
+        _ => return Ok(None),
+    };
+    let dynamic = elf.dynamic()?;
+    let bind_now = dynamic.iter().any(|&(tag, val)| match tag {
+        DT_BIND_NOW => true,
+        DT_FLAGS => val & DF_BIND_NOW != 0,
+        DT_FLAGS_1 => val & DF_1_NOW != 0,
+        _ => false,
+    });
+    let relro = match (elf.phdrs.iter().any(|p| p.p_type == PT_GNU_RELRO), bind_now) {
+        (false, _) => Relro::None,
+        (true, false) => Relro::Partial,
+        (true, true) => Relro::Full,
+    };
+    let symbols = elf.symbols()?;
+    let canary = symbols.iter().any(|s| s.starts_with("__stack_chk_"));
+    let fortify = symbols
+        .iter()
+        .any(|s| s.starts_with("__") && s.ends_with("_chk") && !s.starts_with("__stack_chk"));
+    let nx = elf
+        .phdrs
+        .iter()
+        .any(|p| p.p_type == PT_GNU_STACK && p.flags & PF_X == 0);
+    Ok(Some(Hardening {
+        pie,
+        relro,
+        bind_now,
+        canary,
+        fortify,
+        nx,
+    }))
+}
+
+impl fmt::Display for Hardening {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pie {
+            Some(true) => write!(f, "PIE")?,
+            Some(false) => write!(f, "no PIE")?,
+            None => write!(f, "DSO")?,
+        }
+        match self.relro {
+            Relro::None => write!(f, ", no RELRO")?,
+            Relro::Partial => write!(f, ", partial RELRO")?,
+            Relro::Full => write!(f, ", full RELRO")?,
+        }
+        let flags = [
+            (self.bind_now, "BIND_NOW"),
+            (self.canary, "canary"),
+            (self.fortify, "FORTIFY"),
+            (self.nx, "NX"),
+        ];
+        for (set, name) in flags {
+            if set {
+                write!(f, ", {name}")?;
+            } else {
+                write!(f, ", no {name}")?;
+            }
+        }
+        Ok(())
+    }
+}
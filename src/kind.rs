@@ -0,0 +1,59 @@
+//! What an ELF file is: an executable, a shared library, an object, a kernel module,
+//! or a file for another architecture
+
+use crate::{
+    arch::Arch,
+    elf::{Elf, ReadAt, Type},
+};
+use clap::ArgEnum;
+use serde::Serialize;
+use std::{fmt, io::Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ArgEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// PIE executable
+    Pie,
+    /// non-PIE executable
+    Exec,
+    /// shared library
+    Lib,
+    /// relocatable object, e.g. a member of a static archive
+    Object,
+    /// relocatable object with `.modinfo`
+    Module,
+    /// for an architecture the system can't run, e.g. firmware or the libraries of a cross
+    /// toolchain's target
+    Foreign,
+    /// core dump or an OS specific type
+    Other,
+}
+
+/// `arch` is the one of native files
+pub fn classify<R: ReadAt>(elf: &Elf<R>, arch: &Arch) -> Result<Kind> {
+    if !arch.is_native(elf.machine, elf.class, elf.endian) {
+        return Ok(Kind::Foreign);
+    }
+    Ok(match elf.e_type {
+        Type::Exec => Kind::Exec,
+        Type::Dyn if elf.is_pie()? => Kind::Pie,
+        Type::Dyn => Kind::Lib,
+        Type::Rel if elf.sections()?.iter().any(|s| s.name == ".modinfo") => Kind::Module,
+        Type::Rel => Kind::Object,
+        Type::Core | Type::Other(_) => Kind::Other,
+    })
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Pie => "PIE executable",
+            Kind::Exec => "non-PIE executable",
+            Kind::Lib => "shared library",
+            Kind::Object => "relocatable object",
+            Kind::Module => "kernel module",
+            Kind::Foreign => "foreign architecture",
+            Kind::Other => "other ELF type",
+        })
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{
+    elf::{Class, Elf, ReadAt},
+    root::Root,
+};
+use color_eyre::eyre::Context;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{ErrorKind as ioErrorKind, Result},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+const LD_SO_CONF: &str = "/etc/ld.so.conf";
+const EM_X86_64: u16 = 62;
+
+/// `*` and `?` wildcards, enough for `include /etc/ld.so.conf.d/*.conf`
+fn wildcard(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            wildcard(rest, name) || (!name.is_empty() && wildcard(pattern, &name[1..]))
+        }
+        (Some((b'?', rest)), Some((_, name))) => wildcard(rest, name),
+        (Some((p, rest)), Some((n, name))) if p == n => wildcard(rest, name),
+        _ => false,
+    }
+}
+
+/// Files matching an `include` pattern, the wildcards may only be in the file name
+fn glob(pattern: &Path) -> Vec<String> {
+    let (Some(dir), Some(name)) = (pattern.parent(), pattern.file_name()) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| wildcard(name.as_encoded_bytes(), e.file_name().as_encoded_bytes()))
+        .map(|e| e.path().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Library directories listed in ld.so.conf, following `include` directives,
+/// whose absolute patterns are below `root`
+fn parse_conf(path: &Path, root: &Root, depth: usize, dirs: &mut Vec<String>) {
+    let conf = match fs::read_to_string(path) {
+        Ok(conf) => conf,
+        Err(e) if e.kind() == ioErrorKind::NotFound => return,
+        Err(e) => Err(e)
+            .with_context(|| format!("Failed to read file: {}", path.display()))
+            .unwrap(),
+    };
+    for line in conf.lines() {
+        let line = line.split('#').next().unwrap().trim();
+        if let Some(pattern) = line.strip_prefix("include") {
+            if depth >= 8 {
+                continue;
+            }
+            for pattern in pattern.split_whitespace() {
+                let pattern = match pattern.starts_with('/') {
+                    true => PathBuf::from(root.path(pattern)),
+                    false => path.parent().unwrap_or(Path::new("/")).join(pattern),
+                };
+                for file in glob(&pattern) {
+                    parse_conf(Path::new(&file), root, depth + 1, dirs);
+                }
+            }
+        } else if !line.starts_with("hwcap") {
+            dirs.extend(
+                line.split(|c: char| c.is_whitespace() || c == ':' || c == ',')
+                    .filter(|d| !d.is_empty())
+                    .map(|d| d.to_owned()),
+            );
+        }
+    }
+}
+
+/// glibc's built-in search path, libraries of the wrong class or machine are skipped anyway
+fn default_dirs(class: Class, machine: u16) -> &'static [&'static str] {
+    match class {
+        Class::Elf64 => &["/lib64", "/usr/lib64", "/lib", "/usr/lib"],
+        Class::Elf32 if machine == EM_X86_64 => &["/libx32", "/usr/libx32"],
+        Class::Elf32 => &["/lib", "/usr/lib", "/lib32", "/usr/lib32"],
+    }
+}
+
+/// A library `--links-to` looks for
+pub enum Target {
+    Soname(String),
+    /// canonical path of a library, or of every elf file of a package
+    Paths(HashSet<PathBuf>),
+}
+
+impl Target {
+    fn matches(&self, soname: &str, lib: Option<&str>) -> bool {
+        match self {
+            Target::Soname(target) => {
+                soname == target
+                    || lib.and_then(|l| Path::new(l).file_name()) == Some(target.as_ref())
+            }
+            Target::Paths(paths) => lib
+                .and_then(|l| fs::canonicalize(l).ok())
+                .is_some_and(|l| paths.contains(&l)),
+        }
+    }
+}
+
+/// DT_NEEDED of an object and the paths they resolve to
+type Dependencies = Vec<(String, Option<String>)>;
+
+/// Resolves DT_NEEDED like ld.so does, without ld.so.cache.
+/// Objects and the libraries they resolve to have paths below `ROOT`.
+pub struct Resolver {
+    root: Root,
+    conf_dirs: Vec<String>,
+    /// class and machine of every library looked at, `None` if it isn't there or isn't ELF
+    cache: Mutex<HashMap<String, Option<(Class, u16)>>>,
+    /// dependencies of every library looked at
+    deps: Mutex<HashMap<String, Arc<Dependencies>>>,
+}
+
+impl Resolver {
+    pub fn new(root: &Root) -> Self {
+        let mut conf_dirs = Vec::new();
+        let conf = root.prefixed(LD_SO_CONF);
+        parse_conf(Path::new(&conf), root, 0, &mut conf_dirs);
+        Resolver {
+            root: root.clone(),
+            conf_dirs,
+            cache: Mutex::new(HashMap::new()),
+            deps: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn library(&self, path: &str) -> Option<(Class, u16)> {
+        if let Some(lib) = self.cache.lock().unwrap().get(path) {
+            return *lib;
+        }
+        let lib = File::open(path)
+            .ok()
+            .and_then(|file| Elf::parse(file).ok())
+            .map(|elf| (elf.class, elf.machine));
+        self.cache.lock().unwrap().insert(path.to_owned(), lib);
+        lib
+    }
+
+    /// Path `soname` resolves to for an object at `path`, `None` if it can't be found
+    fn resolve<R: ReadAt>(
+        &self,
+        path: &str,
+        elf: &Elf<R>,
+        rpath: &[String],
+        runpath: &[String],
+        soname: &str,
+    ) -> Option<String> {
+        let compatible = |lib: &str| self.library(lib) == Some((elf.class, elf.machine));
+        if soname.contains('/') {
+            let lib = self.root.path(soname);
+            return compatible(&lib).then_some(lib);
+        }
+        let origin = Path::new(self.root.strip(path))
+            .parent()
+            .map_or("/".into(), |p| p.to_string_lossy());
+        let lib = match elf.class {
+            Class::Elf32 => "lib",
+            Class::Elf64 => "lib64",
+        };
+        let expand = |dir: &String| {
+            dir.replace("${ORIGIN}", &origin)
+                .replace("$ORIGIN", &origin)
+                .replace("${LIB}", lib)
+                .replace("$LIB", lib)
+        };
+        // DT_RPATH is ignored if there is a DT_RUNPATH
+        let rpath = if runpath.is_empty() { rpath } else { &[] };
+        rpath
+            .iter()
+            .chain(runpath)
+            .map(expand)
+            .chain(self.conf_dirs.iter().cloned())
+            .map(|dir| self.root.path(&dir))
+            .chain(
+                default_dirs(elf.class, elf.machine)
+                    .iter()
+                    .map(|d| self.root.prefixed(d)),
+            )
+            .map(|dir| format!("{}/{soname}", dir.trim_end_matches('/')))
+            .find(|lib| compatible(lib))
+    }
+
+    fn dependencies<R: ReadAt>(&self, path: &str, elf: &Elf<R>) -> Result<Dependencies> {
+        let dynamic = elf.dynamic_strings()?;
+        Ok(dynamic
+            .needed
+            .into_iter()
+            .map(|soname| {
+                let lib = self.resolve(path, elf, &dynamic.rpath, &dynamic.runpath, &soname);
+                (soname, lib)
+            })
+            .collect())
+    }
+
+    /// Dependencies of a library found while walking DT_NEEDED, empty if it can't be parsed
+    fn library_dependencies(&self, lib: &str) -> Arc<Dependencies> {
+        if let Some(deps) = self.deps.lock().unwrap().get(lib) {
+            return deps.clone();
+        }
+        let deps = File::open(lib)
+            .ok()
+            .and_then(|file| Elf::parse(file).ok())
+            .and_then(|elf| self.dependencies(lib, &elf).ok())
+            .unwrap_or_default();
+        let deps = Arc::new(deps);
+        self.deps
+            .lock()
+            .unwrap()
+            .insert(lib.to_owned(), deps.clone());
+        deps
+    }
+
+    /// DT_NEEDED entries of the object at `path` which can't be resolved
+    pub fn missing<R: ReadAt>(&self, path: &str, elf: &Elf<R>) -> Result<Vec<String>> {
+        Ok(self
+            .dependencies(path, elf)?
+            .into_iter()
+            .filter_map(|(soname, lib)| lib.is_none().then_some(soname))
+            .collect())
+    }
+
+    /// Whether the object at `path` needs `target`, directly or through other libraries
+    pub fn links_to<R: ReadAt>(
+        &self,
+        path: &str,
+        elf: &Elf<R>,
+        target: &Target,
+        direct: bool,
+    ) -> Result<bool> {
+        let mut queue = self.dependencies(path, elf)?;
+        let mut seen = HashSet::new();
+        while let Some((soname, lib)) = queue.pop() {
+            if target.matches(&soname, lib.as_deref()) {
+                return Ok(true);
+            }
+            match lib {
+                Some(lib) if !direct && seen.insert(lib.clone()) => {
+                    queue.extend(self.library_dependencies(&lib).iter().cloned())
+                }
+                _ => {}
+            }
+        }
+        Ok(false)
+    }
+}
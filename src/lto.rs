@@ -0,0 +1,60 @@
+//! LTO bytecode in objects and static archives, which only the compiler version writing it can
+//! read: GCC's `.gnu.lto_*` sections, clang's `.llvm.lto` section and plain LLVM bitcode files
+
+use crate::{
+    bitcode::{self, BITCODE_MAGIC, WRAPPER_MAGIC},
+    compiler::{self, Compiler},
+    elf::{Elf, ReadAt, ELF_MAGIC},
+};
+use std::{collections::BTreeSet, io::Result};
+
+/// First `len` bytes, fewer if the file is smaller
+fn head<R: ReadAt>(reader: &R, len: u64) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; len.min(reader.size()?) as usize];
+    reader.read_exact_at(&mut buf, 0)?;
+    Ok(buf)
+}
+
+fn bitcode_compiler(data: &[u8]) -> Option<Compiler> {
+    Compiler::parse(&bitcode::producer(data)?)
+}
+
+/// Compilers which wrote the LTO bytecode of an object or a bitcode file, `None` if there is
+/// none. The set is empty if the compiler is unknown.
+pub fn check<R: ReadAt>(reader: R) -> Result<Option<BTreeSet<Compiler>>> {
+    let head = head(&reader, bitcode::HEAD_SIZE)?;
+    if head.starts_with(&BITCODE_MAGIC) || head.starts_with(&WRAPPER_MAGIC) {
+        return Ok(Some(bitcode_compiler(&head).into_iter().collect()));
+    }
+    if !head.starts_with(&ELF_MAGIC) {
+        return Ok(None);
+    }
+    let elf = Elf::parse(reader)?;
+    let sections = elf.sections()?;
+    let gcc = sections.iter().any(|s| s.name.starts_with(".gnu.lto_"));
+    let llvm = sections
+        .iter()
+        .find(|s| s.name == ".llvm.lto" && s.has_data());
+    if !gcc && llvm.is_none() {
+        return Ok(None);
+    }
+    let mut compilers = compiler::compilers(&elf)?;
+    if let Some(s) = llvm {
+        let data = elf.read(s.offset, s.size.min(bitcode::HEAD_SIZE))?;
+        compilers.extend(bitcode_compiler(&data));
+    }
+    Ok(Some(compilers))
+}
+
+/// Compilers which wrote the LTO bytecode of the members of a static archive, like `check`
+pub fn check_archive<R: ReadAt>(
+    members: impl IntoIterator<Item = R>,
+) -> Result<Option<BTreeSet<Compiler>>> {
+    let mut found: Option<BTreeSet<_>> = None;
+    for member in members {
+        if let Some(compilers) = check(member)? {
+            found.get_or_insert_default().extend(compilers);
+        }
+    }
+    Ok(found)
+}
@@ -2,13 +2,54 @@ use clap::Parser;
 use color_eyre::eyre::Context;
 use rayon::prelude::*;
 use std::{
-    fs::File,
-    io::{ErrorKind as ioErrorKind, Read},
-    process::Command,
-    str,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs::{self, File},
+    io::{self, ErrorKind as ioErrorKind},
+    path::Path,
+    sync::{Arc, Mutex},
 };
 
-const ELF_HEADER: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+mod ar;
+mod arch;
+mod atom;
+mod binpkg;
+mod bitcode;
+mod cet;
+mod compiler;
+mod compress;
+mod deps;
+mod dwarf;
+mod elf;
+mod emerge_log;
+mod flags;
+mod hardening;
+mod kind;
+mod ldso;
+mod lto;
+mod make_conf;
+mod merged;
+mod module;
+mod package_env;
+mod prebuilt;
+mod report;
+mod root;
+mod schedule;
+mod tiers;
+mod vdb;
+
+use ar::Slice;
+use arch::Arch;
+use binpkg::Origin;
+use cet::X86Features;
+use compiler::Compiler;
+use compress::{Compression, Input};
+use elf::{Elf, ElfInfo};
+use emerge_log::BuildTime;
+use flags::BuildFlags;
+use hardening::{Check, Hardening};
+use kind::Kind;
+use report::{FileReport, Format, PackageReport, RebuildReport};
+use root::Root;
 
 #[derive(Debug, Parser)]
 struct Arg {
@@ -16,54 +57,235 @@ struct Arg {
     #[clap(short, long)]
     file: bool,
 
-    /// print average build time of package using qlop
+    /// print ELF header info next to elf files
+    #[clap(short, long, requires = "file")]
+    info: bool,
+
+    /// print build time of package from emerge.log
     #[clap(short, long)]
     time: bool,
 
+    /// which build time statistic to print and to sort by
+    #[clap(long, arg_enum, default_value = "average")]
+    stat: emerge_log::Stat,
+
+    /// only packages merged before a date, e.g. `2024-03-01`, `2024-03-01 12:30` (UTC) or
+    /// `@1709251200`, or before the installed version of a package was merged, e.g. sys-devel/gcc
+    #[clap(long, value_name = "DATE|PACKAGE")]
+    built_before: Option<merged::Moment>,
+
+    /// only packages merged after a date or after the installed version of a package
+    #[clap(long, value_name = "DATE|PACKAGE")]
+    built_after: Option<merged::Moment>,
+
+    /// print CFLAGS, CXXFLAGS, LDFLAGS, CHOST and CBUILD the packages were built with
+    #[clap(long)]
+    flags: bool,
+
+    /// only packages built with flags other than those set in the profile, make.conf and
+    /// package.env
+    #[clap(long)]
+    flags_differ: bool,
+
+    /// only packages with env files in package.env setting flags other than they were built with
+    #[clap(long)]
+    env_drift: bool,
+
+    /// only packages built without this flag in CFLAGS, CXXFLAGS or LDFLAGS, e.g. -flto
+    #[clap(long, value_name = "FLAG", allow_hyphen_values = true)]
+    missing_flag: Vec<String>,
+
+    /// only count elf files of these kinds, packages without any are left out,
+    /// `foreign` implies --foreign
+    #[clap(long, arg_enum, use_value_delimiter = true, value_name = "KIND")]
+    kind: Vec<Kind>,
+
+    /// don't count elf files of these kinds, e.g. `foreign,module`
+    #[clap(long, arg_enum, use_value_delimiter = true, value_name = "KIND")]
+    exclude_kind: Vec<Kind>,
+
+    /// CHOST of native elf files, e.g. aarch64-unknown-linux-gnu, CHOST of the profile or
+    /// make.conf by default, else with --root or --eprefix the CHOST most packages were built for,
+    /// else the architecture of binarypkg
+    #[clap(long, value_name = "CHOST")]
+    target: Option<Arch>,
+
+    /// keep packages whose elf files are all for foreign architectures, e.g. firmware
+    #[clap(long)]
+    foreign: bool,
+
+    /// print whether packages were built locally or installed from a binhost
+    #[clap(long)]
+    origin: bool,
+
+    /// only packages built locally or installed from a binhost of our own or the official
+    /// Gentoo binhost, e.g. `binhost,gentoo`
+    #[clap(long, arg_enum, use_value_delimiter = true, value_name = "ORIGIN")]
+    installed_from: Vec<Origin>,
+
     /// print rebuild package command line
     #[clap(short, long)]
     rebuild: bool,
 
+    /// rebuild tier `NAME MAX_TIME [JOBS [LOAD_AVERAGE [EMERGE_ARGS...]]]`, e.g. 'small 1m 16 20',
+    /// `-` for no limit or an unset field; replaces the tiers of --config, which default to
+    /// 60s and 15min with jobs and load average derived from MAKEOPTS and EMERGE_DEFAULT_OPTS
+    #[clap(long, value_name = "TIER")]
+    tier: Vec<tiers::Tier>,
+
+    /// extra arguments of every rebuild command line
+    #[clap(long, value_name = "ARGS", allow_hyphen_values = true)]
+    emerge_args: Option<String>,
+
+    /// instead of tiers, spread the rebuild over this many emerge commands to run at the same
+    /// time, balanced by build time, and print the estimated finish time. A command waits only
+    /// for the commands rebuilding its dependencies
+    #[clap(long, value_name = "JOBS")]
+    schedule: Option<usize>,
+
+    /// cores shared by the emerge commands of --schedule, all of them by default
+    #[clap(long, requires = "schedule")]
+    cores: Option<u32>,
+
+    /// config file with `tier <TIER>` and `emerge-args <ARGS>` lines,
+    /// /etc/portage/binarypkg.conf if it exists by default
+    #[clap(long, value_name = "FILE")]
+    config: Option<String>,
+
+    /// print compilers which produced the elf files, from .comment and DWARF
+    #[clap(short, long)]
+    compiler: bool,
+
+    /// only packages with code from this compiler, e.g. `gcc<14`, `clang>=17`
+    #[clap(long, value_name = "COMPILER")]
+    built_with: Vec<compiler::Requirement>,
+
+    /// audit PIE, RELRO, BIND_NOW, stack protector, FORTIFY and NX stack,
+    /// only keep packages failing one of --hardening-checks
+    #[clap(long)]
+    hardening: bool,
+
+    /// which hardening checks a package must pass
+    #[clap(
+        long,
+        arg_enum,
+        use_value_delimiter = true,
+        default_value = "pie,relro,nx"
+    )]
+    hardening_checks: Vec<Check>,
+
+    /// only keep packages with x86 elf files lacking IBT or SHSTK
+    #[clap(long)]
+    cet: bool,
+
+    /// only keep packages with x86-64 elf files needing a lower ISA level than this,
+    /// e.g. 3 for x86-64-v3; files without a recorded level don't count, GCC only records
+    /// the level needed with -mneeded and otherwise the level of the instructions used
+    #[clap(long, value_name = "LEVEL", possible_values = ["1", "2", "3", "4"])]
+    isa_level: Option<u8>,
+
+    /// only keep packages installing static archives or objects with LTO bytecode,
+    /// and print the compilers which wrote it
+    #[clap(long)]
+    lto: bool,
+
+    /// only keep packages with elf files needing libraries which can't be found,
+    /// and print the rebuild command line; foreign elf files, e.g. of crossdev sysroots,
+    /// are only checked with --foreign
+    #[clap(long)]
+    broken: bool,
+
+    /// only keep packages installing kernel modules built for another kernel than the one in
+    /// /usr/src/linux, or the running one if it wasn't built, and print the rebuild command line
+    #[clap(long)]
+    modules: bool,
+
+    /// also rebuild prebuilt packages, e.g. -bin packages, which are only labeled otherwise
+    /// as rebuilding them installs the same binaries again
+    #[clap(long)]
+    prebuilt: bool,
+
+    /// only keep packages linking against a library, given as soname, path or package,
+    /// and print the rebuild command line
+    #[clap(long, value_name = "LIB")]
+    links_to: Option<String>,
+
+    /// with --links-to, only count libraries in DT_NEEDED, not their dependencies
+    #[clap(long, requires = "links-to")]
+    direct: bool,
+
+    /// output format, json and jsonl always include build times and elf files
+    #[clap(long, arg_enum, default_value = "text")]
+    format: Format,
+
+    /// inspect the system at this directory, e.g. a chroot or the rootfs of a container:
+    /// read its VDB, emerge.log and config, and set ROOT in the rebuild command lines.
+    /// File paths are printed with the directory in front.
+    #[clap(long, value_name = "DIR")]
+    root: Option<String>,
+
+    /// offset of a Gentoo Prefix install, below --root if given
+    #[clap(long, value_name = "DIR")]
+    eprefix: Option<String>,
+
     /// only process one package (CAT/PN or PN without PV)
     atom: Option<String>,
 }
 
-fn eix(input: Option<String>) -> Vec<String> {
-    let mut cmd = Command::new("eix");
-    cmd.arg("-I#");
-    if let Some(input) = input {
-        cmd.arg(input);
+/// Members of the archives opened so far by name, so the member table of an archive is only
+/// parsed once instead of once for every member
+static ARCHIVES: Mutex<BTreeMap<String, Arc<HashMap<String, ar::Member>>>> =
+    Mutex::new(BTreeMap::new());
+
+/// Members of the archive `archive` stored at `path`, by name
+fn members(path: &str, archive: &Slice<File>) -> io::Result<Arc<HashMap<String, ar::Member>>> {
+    if let Some(members) = ARCHIVES.lock().unwrap().get(path) {
+        return Ok(members.clone());
     }
-    let output = cmd.output().unwrap();
-    if !output.status.success() {
-        if output.status.code() == Some(1) {
-            Vec::new()
-        } else {
-            panic!(
-                "eix failed!, stdout:\n{:?}\nstderr:\n{:?}",
-                String::from_utf8_lossy(&output.stdout),
-                String::from_utf8_lossy(&output.stderr)
-            )
-        }
-    } else {
-        let output = str::from_utf8(&output.stdout).unwrap();
-        output
-            .split_terminator('\n')
-            .map(|s| s.to_owned())
-            .collect()
+    let mut members = HashMap::new();
+    for member in ar::members(archive)? {
+        // like binutils, the first of members with the same name wins
+        members.entry(member.name.clone()).or_insert(member);
     }
+    let members = Arc::new(members);
+    ARCHIVES
+        .lock()
+        .unwrap()
+        .insert(path.to_owned(), members.clone());
+    Ok(members)
 }
 
-fn is_elf_file(path: &str) -> bool {
-    let mut file = File::open(&path)
-        .with_context(|| format!("Failed to open file: {path}"))
+/// `path` may also be a member of a static archive, e.g. `/usr/lib64/libfoo.a(foo.o)`
+fn open(path: &str) -> io::Result<Slice<File>> {
+    let (file, member) = match ar::split_path(path) {
+        Some((archive, member)) => (archive, Some(member)),
+        None => (path, None),
+    };
+    let reader = File::open(file)
+        .with_context(|| format!("Failed to open file: {file}"))
         .unwrap();
-    let mut buf = [0u8; 4];
-    match file.read_exact(&mut buf) {
-        Ok(_) => buf == ELF_HEADER,
+    Slice::file(reader).and_then(|archive| match member {
+        Some(name) => {
+            let members = members(file, &archive)?;
+            let member = members
+                .get(name)
+                .ok_or_else(|| io::Error::new(ioErrorKind::InvalidData, "no such member"))?;
+            archive.member(Path::new(file), member)
+        }
+        None => Ok(archive),
+    })
+}
+
+/// `None` if the file isn't well-formed, panics on other errors
+fn check<T>(path: &str, result: io::Result<T>) -> Option<T> {
+    match result {
+        Ok(t) => Some(t),
         Err(e) => {
-            if e.kind() == ioErrorKind::UnexpectedEof {
-                false
+            if matches!(
+                e.kind(),
+                ioErrorKind::UnexpectedEof | ioErrorKind::InvalidData
+            ) {
+                None
             } else {
                 Err(e)
                     .with_context(|| format!("Failed to read file: {path}"))
@@ -73,51 +295,238 @@ fn is_elf_file(path: &str) -> bool {
     }
 }
 
-fn qlist(pkg: &str) -> Vec<String> {
-    let mut cmd = Command::new("qlist");
-    cmd.arg("-eo");
-    cmd.arg(pkg);
-    let output = cmd.output().unwrap();
-    if !output.status.success() {
-        panic!(
-            "qlist {pkg} failed!, stdout:\n{:?}\nstderr:\n{:?}",
-            String::from_utf8_lossy(&output.stdout),
-            String::from_utf8_lossy(&output.stderr)
-        )
+/// `None` if the file isn't well-formed, see `open` for `path`.
+/// Compressed files are read decompressed.
+fn read_file<T>(path: &str, f: impl FnOnce(Input<Slice<File>>) -> io::Result<T>) -> Option<T> {
+    check(path, open(path).and_then(Input::new).and_then(f))
+}
+
+/// `None` if the file isn't a well-formed ELF file
+fn read_elf<T>(path: &str, f: impl FnOnce(&Elf<Input<Slice<File>>>) -> io::Result<T>) -> Option<T> {
+    read_file(path, |file| Elf::parse(file).and_then(|elf| f(&elf)))
+}
+
+fn elf_info(path: &str) -> Option<ElfInfo> {
+    read_elf(path, |elf| elf.info())
+}
+
+/// Compressed files only have their ELF header checked, without decompressing all of them
+fn is_elf_file(path: &str) -> bool {
+    let probe = open(path).and_then(|file| compress::probe(&file, elf::IDENT_SIZE as u64));
+    match check(path, probe) {
+        Some(Some((_, head))) => elf::is_elf_ident(&head),
+        Some(None) => elf_info(path).is_some(),
+        None => false,
     }
-    let output = str::from_utf8(&output.stdout).unwrap();
-    output
-        .split_terminator('\n')
-        .map(|s| s.to_owned())
-        .collect()
 }
 
-fn qlop(pkg: &str) -> u64 {
-    let mut cmd = Command::new("qlop");
-    cmd.arg("-CMamq");
-    cmd.arg(pkg);
-    let output = cmd.output().unwrap();
-    if !output.status.success() {
-        panic!(
-            "qlop {pkg} failed!, stdout:\n{:?}\nstderr:\n{:?}",
-            String::from_utf8_lossy(&output.stdout),
-            String::from_utf8_lossy(&output.stderr)
+/// Only the magic is checked, without decompressing the file
+fn compression(path: &str) -> Option<Compression> {
+    let probe = open(path).and_then(|file| compress::probe(&file, 0));
+    check(path, probe)
+        .flatten()
+        .map(|(compression, _)| compression)
+}
+
+fn kind(path: &str, arch: &Arch) -> Option<Kind> {
+    read_elf(path, |elf| kind::classify(elf, arch))
+}
+
+fn is_native(path: &str, arch: &Arch) -> bool {
+    read_elf(path, |elf| {
+        Ok(arch.is_native(elf.machine, elf.class, elf.endian))
+    })
+    .unwrap_or(false)
+}
+
+fn compilers(path: &str) -> BTreeSet<Compiler> {
+    read_elf(path, compiler::compilers).unwrap_or_default()
+}
+
+fn hardening(path: &str) -> Option<Hardening> {
+    read_elf(path, hardening::check).flatten()
+}
+
+fn x86_features(path: &str) -> Option<X86Features> {
+    read_elf(path, cet::check).flatten()
+}
+
+/// The members of thin archives are opened from their own files
+fn lto(path: &str) -> Option<BTreeSet<Compiler>> {
+    if !path.ends_with(".a") {
+        return read_file(path, lto::check).flatten();
+    }
+    let archive = Path::new(path);
+    let found = open(path).and_then(|file| {
+        let members = members(path, &file)?;
+        // a missing member of a thin archive has no bytecode either
+        lto::check_archive(
+            members
+                .values()
+                .filter_map(|m| file.member(archive, m).ok()),
         )
+    });
+    check(path, found).flatten()
+}
+
+fn module_release(path: &str) -> Option<String> {
+    read_elf(path, module::release).flatten()
+}
+
+fn missing_libs(path: &str, resolver: &ldso::Resolver) -> Vec<String> {
+    read_elf(path, |elf| resolver.missing(path, elf)).unwrap_or_default()
+}
+
+fn links_to(path: &str, resolver: &ldso::Resolver, target: &ldso::Target, direct: bool) -> bool {
+    read_elf(path, |elf| resolver.links_to(path, elf, target, direct)).unwrap_or(false)
+}
+
+/// `--links-to` target and the packages providing it, a path is one of `root`
+fn links_to_target(lib: &str, root: &Root) -> (ldso::Target, Vec<String>) {
+    if lib.starts_with('/') {
+        let lib = root.path(lib);
+        let path = fs::canonicalize(&lib)
+            .with_context(|| format!("Failed to resolve path: {lib}"))
+            .unwrap();
+        (ldso::Target::Paths(HashSet::from([path])), Vec::new())
+    } else if lib.contains(".so") && !lib.contains('/') {
+        (ldso::Target::Soname(lib.to_owned()), Vec::new())
+    } else {
+        let pkgs = vdb::packages(root, Some(lib));
+        if pkgs.is_empty() {
+            panic!("No installed package matches {lib}");
+        }
+        let paths = pkgs
+            .par_iter()
+            .flat_map(list_binary)
+            .filter_map(|f| fs::canonicalize(f).ok())
+            .collect();
+        let atoms = pkgs.into_iter().map(|p| p.atom).collect();
+        (ldso::Target::Paths(paths), atoms)
     }
-    let output = str::from_utf8(&output.stdout).unwrap();
-    let num = output.split_whitespace().nth(1).unwrap_or("0");
-    num.parse().unwrap()
 }
 
-fn list_binary(pkg: &str) -> Vec<String> {
-    qlist(pkg)
+/// `no PIE 2/5`: 2 of 5 files failed a check, `None` if all passed
+fn count_failures<T>(items: &[T], failure: &str, passes: impl Fn(&T) -> bool) -> Option<String> {
+    let n = items.iter().filter(|i| !passes(i)).count();
+    (n != 0).then(|| format!("{failure} {n}/{}", items.len()))
+}
+
+pub(crate) fn join<T: ToString>(items: impl IntoIterator<Item = T>) -> String {
+    items
+        .into_iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// ELF members of a static archive as `archive(member)`, empty if it isn't one
+fn archive_members(path: &str) -> Vec<String> {
+    let archive = Path::new(path);
+    let members = open(path).and_then(|file| {
+        let members = members(path, &file)?;
+        // in the order of the archive
+        let mut members: Vec<_> = members.values().collect();
+        members.sort_unstable_by_key(|m| m.offset);
+        Ok(members
+            .into_iter()
+            .filter(|m| {
+                let elf = file.member(archive, m).and_then(Elf::parse);
+                elf.and_then(|elf| elf.info()).is_ok()
+            })
+            .map(|m| format!("{path}({})", m.name))
+            .collect())
+    });
+    check(path, members).unwrap_or_default()
+}
+
+/// Members of the static archives of an entry, which portage doesn't record in NEEDED.ELF.2
+fn list_archives(entry: &vdb::Entry) -> Vec<String> {
+    entry
+        .objs()
         .into_par_iter()
-        .filter(|p| is_elf_file(&p))
+        .filter(|p| p.ends_with(".a"))
+        .flat_map_iter(|p| archive_members(&p))
+        .collect()
+}
+
+/// Compressed elf files of an entry, e.g. kernel modules, which portage doesn't record in
+/// NEEDED.ELF.2
+fn compressed_elf(entry: &vdb::Entry) -> Vec<String> {
+    entry
+        .objs()
+        .into_par_iter()
+        .filter(|p| compress::EXTENSIONS.iter().any(|e| p.ends_with(e)) && is_elf_file(p))
+        .collect()
+}
+
+/// Use NEEDED.ELF.2 and the compressed files when portage recorded it, open every file otherwise
+fn list_binary(pkg: &vdb::Package) -> Vec<String> {
+    pkg.entries
+        .par_iter()
+        .flat_map(|entry| {
+            let mut list = match entry.needed_elf() {
+                Some(mut needed) => {
+                    needed.extend(compressed_elf(entry));
+                    needed
+                }
+                None => entry
+                    .objs()
+                    .into_par_iter()
+                    .filter(|p| is_elf_file(p))
+                    .collect::<Vec<_>>(),
+            };
+            list.extend(list_archives(entry));
+            list
+        })
+        .collect()
+}
+
+/// Static archives and objects with LTO bytecode, and the compilers which wrote it
+fn list_lto(pkg: &vdb::Package) -> Vec<(String, BTreeSet<Compiler>)> {
+    pkg.entries
+        .par_iter()
+        .flat_map(|entry| entry.objs())
+        .filter(|p| p.ends_with(".a") || p.ends_with(".o"))
+        .filter_map(|p| {
+            let compilers = lto(&p)?;
+            Some((p, compilers))
+        })
         .collect()
 }
 
-fn have_binary(pkg: &str) -> bool {
-    qlist(pkg).into_par_iter().any(|p| is_elf_file(&p))
+/// Out-of-tree kernel modules and the kernel release they were built for
+fn list_modules(pkg: &vdb::Package) -> Vec<(String, Option<String>)> {
+    pkg.entries
+        .par_iter()
+        .flat_map(|entry| entry.objs())
+        .filter(|p| module::is_module(p))
+        .map(|p| {
+            let release = module_release(&p);
+            (p, release)
+        })
+        .collect()
+}
+
+/// Whether a package has elf files, only counting native ones if `arch` is given
+fn have_binary(pkg: &vdb::Package, arch: Option<&Arch>) -> bool {
+    let counts = |path: &str| arch.is_none_or(|arch| is_native(path, arch));
+    pkg.entries.par_iter().any(|entry| {
+        let elf = match entry.needed_elf() {
+            Some(needed) => {
+                needed.iter().any(|p| counts(p)) || compressed_elf(entry).iter().any(|p| counts(p))
+            }
+            None => entry
+                .objs()
+                .into_par_iter()
+                .any(|p| is_elf_file(&p) && counts(&p)),
+        };
+        elf || entry
+            .objs()
+            .into_par_iter()
+            .filter(|p| p.ends_with(".a"))
+            .any(|p| archive_members(&p).iter().any(|m| counts(m)))
+    })
 }
 
 fn print_time(sec: u64) -> String {
@@ -130,78 +539,548 @@ fn print_time(sec: u64) -> String {
     }
 }
 
+/// Everything asked about one elf file
+fn file_report(
+    path: &str,
+    opt: &Arg,
+    arch: &Arch,
+    resolver: Option<&ldso::Resolver>,
+    target: Option<&ldso::Target>,
+) -> FileReport {
+    let json = opt.format != Format::Text;
+    FileReport {
+        path: path.to_owned(),
+        kind: kind(path, arch),
+        compression: compression(path),
+        elf: (opt.info || json).then(|| elf_info(path)).flatten(),
+        compilers: opt.compiler.then(|| compilers(path)),
+        hardening: opt.hardening.then(|| hardening(path)).flatten(),
+        x86: (opt.cet || opt.isa_level.is_some())
+            .then(|| x86_features(path))
+            .flatten(),
+        lto: opt.lto.then(|| lto(path)).flatten(),
+        kernel: (opt.modules && module::is_module(path))
+            .then(|| module_release(path))
+            .flatten(),
+        links_to: resolver
+            .zip(target)
+            .map(|(resolver, target)| links_to(path, resolver, target, opt.direct)),
+        missing: resolver
+            .filter(|_| opt.broken && (opt.foreign || is_native(path, arch)))
+            .map(|resolver| missing_libs(path, resolver)),
+    }
+}
+
+struct Pkg {
+    atom: String,
+    files: Vec<String>,
+    build_time: Option<BuildTime>,
+    time: u64,
+    compilers: BTreeSet<Compiler>,
+    /// e.g. `no PIE 2/5`
+    problems: Vec<String>,
+    /// CAT/PN of DEPEND, RDEPEND and BDEPEND
+    deps: Vec<String>,
+    flags: Option<BuildFlags>,
+    origin: Option<Origin>,
+    prebuilt: Option<prebuilt::Reason>,
+}
+
+/// Entry of the most recent build of a package
+fn latest_entry(pkg: &vdb::Package) -> &vdb::Entry {
+    pkg.entries.iter().max_by_key(|e| e.build_time()).unwrap()
+}
+
+/// Emerge commands rebuilding `pkgs` after their dependencies, and the tier of every package.
+/// Prebuilt packages are left out unless `--prebuilt` is given.
+fn plan(
+    pkgs: &[Pkg],
+    opt: &Arg,
+    tiers: &tiers::Tiers,
+    make_conf: &HashMap<String, String>,
+    root: &Root,
+) -> (Vec<RebuildReport>, HashMap<String, String>) {
+    let (pkgs, prebuilt): (Vec<_>, Vec<_>) = pkgs
+        .iter()
+        .partition(|p| opt.prebuilt || !p.prebuilt.is_some_and(|r| r.is_prebuilt()));
+    if !prebuilt.is_empty() {
+        eprintln!(
+            "Not rebuilding prebuilt packages, see --prebuilt: {}",
+            join(prebuilt.iter().map(|p| &p.atom))
+        );
+    }
+    let index: HashMap<_, _> = pkgs
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (p.atom.as_str(), i))
+        .collect();
+    // dependencies may go through packages which aren't rebuilt, e.g. virtuals
+    let installed: HashMap<_, Vec<_>> = match pkgs.len() {
+        0 | 1 => HashMap::new(),
+        _ => vdb::packages(root, None)
+            .into_par_iter()
+            .filter(|p| !index.contains_key(p.atom.as_str()))
+            .map(|p| {
+                let deps = p.entries.iter().flat_map(|e| e.dependencies()).collect();
+                (p.atom, deps)
+            })
+            .collect(),
+    };
+    let deps = deps::between(pkgs.iter().map(|p| &p.deps), &index, &installed);
+    let order = match opt.schedule {
+        Some(_) => deps::order(&deps, &vec![0; pkgs.len()], false),
+        None => {
+            let tier: Vec<_> = pkgs.iter().map(|p| tiers.position(p.time)).collect();
+            deps::order(&deps, &tier, true)
+        }
+    };
+    for cycle in &order.cycles {
+        eprintln!(
+            "Dependency cycle, rebuilding together: {}",
+            join(cycle.iter().map(|&i| &pkgs[i].atom))
+        );
+    }
+    let stages = order.stage.iter().max().map_or(1, |s| s + 1);
+    let mut rebuilds = Vec::new();
+    let mut tier_of = HashMap::new();
+    if let Some(jobs) = opt.schedule {
+        let cores = opt.cores.unwrap_or_else(tiers::cores);
+        let threads = make_conf::make_jobs(make_conf).unwrap_or(cores);
+        let mut batches = Vec::new();
+        for stage in 0..stages {
+            let mut groups: BTreeMap<_, (Vec<_>, _)> = BTreeMap::new();
+            for (i, p) in pkgs
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| order.stage[i] == stage)
+            {
+                let group = groups.entry(order.group[i]).or_default();
+                group.0.push(p.atom.as_str());
+                group.1 += p.time;
+            }
+            batches.extend(
+                schedule::lpt(groups.into_values(), jobs)
+                    .into_iter()
+                    .map(|b| (stage, b)),
+            );
+        }
+        let mut batch_of = vec![0; pkgs.len()];
+        for (k, (_, batch)) in batches.iter().enumerate() {
+            for a in &batch.atoms {
+                batch_of[index[a]] = k;
+            }
+        }
+        // a command only waits for the commands rebuilding its dependencies, which come before
+        let after: Vec<Vec<_>> = batches
+            .iter()
+            .enumerate()
+            .map(|(k, (_, batch))| {
+                let mut after: Vec<_> = batch
+                    .atoms
+                    .iter()
+                    .flat_map(|a| deps[index[a]].iter().map(|&d| batch_of[d]))
+                    .filter(|&b| b != k)
+                    .collect();
+                after.sort_unstable();
+                after.dedup();
+                after
+            })
+            .collect();
+        let time: Vec<_> = batches.iter().map(|(_, b)| b.time).collect();
+        let finish = schedule::finish_times(&time, &after, jobs, threads, cores);
+        let name = |k: usize| format!("job{}", k + 1);
+        for (k, (stage, batch)) in batches.into_iter().enumerate() {
+            let tier = tiers::Tier::serial(name(k));
+            tier_of.extend(
+                batch
+                    .atoms
+                    .iter()
+                    .map(|&a| (a.to_owned(), tier.name.clone())),
+            );
+            rebuilds.push(RebuildReport {
+                command: tiers.command(&tier, &batch.atoms),
+                tier: tier.name,
+                stage,
+                after: after[k].iter().map(|&b| name(b)).collect(),
+                estimated_time: Some(finish[k]),
+            });
+        }
+    } else {
+        for stage in 0..stages {
+            for (t, tier) in tiers.iter().enumerate() {
+                let atoms: Vec<_> = (0..pkgs.len())
+                    .filter(|&i| order.stage[i] == stage && order.tier[i] == t)
+                    .map(|i| pkgs[i].atom.as_str())
+                    .collect();
+                // later stages only exist for the packages which need them
+                if stage != 0 && atoms.is_empty() {
+                    continue;
+                }
+                tier_of.extend(atoms.iter().map(|&a| (a.to_owned(), tier.name.clone())));
+                rebuilds.push(RebuildReport {
+                    tier: tier.name.clone(),
+                    command: tiers.command(tier, &atoms),
+                    stage,
+                    after: Vec::new(),
+                    estimated_time: None,
+                });
+            }
+        }
+    }
+    (rebuilds, tier_of)
+}
+
 fn main() {
-    let opt = Arg::parse();
+    let mut opt = Arg::parse();
     color_eyre::install().unwrap();
-    let need_time = opt.time || opt.rebuild;
-    let pkgs = eix(opt.atom);
+    // foreign elf files are left out unless --foreign
+    opt.foreign |= opt.kind.contains(&Kind::Foreign);
+    let rebuild = opt.rebuild
+        || opt.broken
+        || opt.links_to.is_some()
+        || opt.modules
+        || opt.schedule.is_some();
+    let json = opt.format != Format::Text;
+    let need_time = opt.time || rebuild || json;
+    let need_compiler = opt.compiler || !opt.built_with.is_empty();
+    let need_x86 = opt.cet || opt.isa_level.is_some();
+    let need_flags = opt.flags || opt.flags_differ || opt.env_drift || !opt.missing_flag.is_empty();
+    let need_kind = !opt.kind.is_empty() || !opt.exclude_kind.is_empty();
+    let need_origin = opt.origin || !opt.installed_from.is_empty();
+    let need_list = opt.file
+        || need_kind
+        || need_compiler
+        || opt.hardening
+        || need_x86
+        || opt.broken
+        || opt.links_to.is_some()
+        || json;
+    let root = Root::new(opt.root.as_deref(), opt.eprefix.as_deref());
+    let make_conf = make_conf::read(&root);
+    let configured_flags = BuildFlags::from_vars(&make_conf);
+    let package_env =
+        (opt.flags_differ || opt.env_drift).then(|| package_env::PackageEnv::read(&root));
+    // the profile sets CHOST, and another system may be of another architecture than
+    // binarypkg, its packages recorded which one
+    let arch = opt.target.unwrap_or_else(|| {
+        make_conf
+            .get("CHOST")
+            .cloned()
+            .or_else(|| (!root.is_host()).then(|| vdb::chost(&root)).flatten())
+            .and_then(|c| c.parse().ok())
+            .unwrap_or_else(Arch::host)
+    });
+    let selected =
+        |k: Kind| (opt.kind.is_empty() || opt.kind.contains(&k)) && !opt.exclude_kind.contains(&k);
+    let kernel = opt.modules.then(|| {
+        module::kernel_release(&root)
+            .expect("Failed to find the kernel release of /usr/src/linux or the running kernel")
+    });
+    let index = need_origin.then(|| binpkg::Index::read(&root));
+    let resolver = (opt.broken || opt.links_to.is_some()).then(|| ldso::Resolver::new(&root));
+    let target = opt
+        .links_to
+        .as_deref()
+        .map(|lib| links_to_target(lib, &root));
+    let built_before = opt.built_before.as_ref().map(|m| m.resolve(&root));
+    let built_after = opt.built_after.as_ref().map(|m| m.resolve(&root));
+    let mut pkgs = vdb::packages(&root, opt.atom.as_deref());
+    if built_before.is_some() || built_after.is_some() {
+        // a package with several slots is kept if one of them matches
+        pkgs.retain(|pkg| {
+            pkg.entries.iter().filter_map(|e| e.build_time()).any(|t| {
+                built_before.is_none_or(|before| t < before)
+                    && built_after.is_none_or(|after| t > after)
+            })
+        });
+    }
+    let times = if need_time {
+        emerge_log::build_times(&root)
+    } else {
+        HashMap::new()
+    };
+    let filtered = opt.hardening
+        || need_x86
+        || need_flags
+        || opt.lto
+        || opt.modules
+        || need_kind
+        || need_origin
+        || built_before.is_some()
+        || built_after.is_some();
+    let output = json || opt.file || opt.time || rebuild || need_compiler || filtered;
     let mut pkgs: Vec<_> = pkgs
         .into_par_iter()
         .filter_map(|pkg| {
-            let (mut list, have) = if opt.file {
-                let list = list_binary(&pkg);
-                let have = !list.is_empty();
+            let mut problems = Vec::new();
+            let origin = index.as_ref().map(|i| i.origin(latest_entry(&pkg)));
+            if origin
+                .is_some_and(|o| !opt.installed_from.is_empty() && !opt.installed_from.contains(&o))
+            {
+                return None;
+            }
+            let flags = need_flags.then(|| BuildFlags::recorded(latest_entry(&pkg)));
+            if let (Some(flags), Some(package_env)) = (&flags, &package_env) {
+                let env_files = package_env.files(&pkg.atom, latest_entry(&pkg));
+                if opt.env_drift && env_files.is_empty() {
+                    return None;
+                }
+                let differences = if env_files.is_empty() {
+                    flags.differences(&configured_flags)
+                } else {
+                    let vars = package_env.apply(&env_files, &make_conf);
+                    flags.differences(&BuildFlags::from_vars(&vars))
+                };
+                if differences.is_empty() {
+                    return None;
+                }
+                problems.extend(differences.iter().map(|var| match env_files.is_empty() {
+                    true => format!("{var} differ"),
+                    false => format!("{var} differ with {}", env_files.join(" ")),
+                }));
+            }
+            if let Some(flags) = flags.as_ref().filter(|_| !opt.missing_flag.is_empty()) {
+                let missing: Vec<_> = opt.missing_flag.iter().filter(|f| !flags.has(f)).collect();
+                if missing.is_empty() {
+                    return None;
+                }
+                problems.extend(missing.iter().map(|f| format!("no {f}")));
+            }
+            let lto = opt.lto.then(|| list_lto(&pkg));
+            if let Some(lto) = &lto {
+                if lto.is_empty() {
+                    return None;
+                }
+                let compilers: BTreeSet<_> = lto.iter().flat_map(|(_, c)| c).collect();
+                problems.push(match compilers.is_empty() {
+                    true => "LTO bytecode".to_owned(),
+                    false => format!("LTO bytecode of {}", join(compilers)),
+                });
+            }
+            let modules = kernel.as_ref().map(|_| list_modules(&pkg));
+            if let (Some(kernel), Some(modules)) = (&kernel, &modules) {
+                let stale: BTreeSet<_> = modules
+                    .iter()
+                    .filter_map(|(_, release)| release.as_ref())
+                    .filter(|&release| release != kernel)
+                    .collect();
+                if stale.is_empty() {
+                    return None;
+                }
+                problems.push(format!("modules for {}", join(stale)));
+            }
+            let (mut list, have) = if need_list {
+                let mut list = list_binary(&pkg);
+                if need_kind {
+                    list.retain(|f| kind(f, &arch).is_some_and(selected));
+                }
+                let have = match opt.foreign {
+                    true => !list.is_empty(),
+                    false => list.par_iter().any(|f| is_native(f, &arch)),
+                };
                 (list, have)
             } else {
-                (Vec::new(), have_binary(&pkg))
+                (
+                    Vec::new(),
+                    have_binary(&pkg, (!opt.foreign).then_some(&arch)),
+                )
             };
-            if have {
-                list.par_sort();
-                let time = if need_time {
-                    qlop(&pkg)
-                } else {
-                    0
-                };
-                Some((pkg, list, time))
+            // LTO bytecode and modules count even without elf files, e.g. in LLVM bitcode
+            // archives or modules of another architecture
+            if !have && lto.is_none() && modules.is_none() {
+                return None;
+            }
+            list.extend(lto.into_iter().flatten().map(|(path, _)| path));
+            list.extend(modules.into_iter().flatten().map(|(path, _)| path));
+            list.par_sort();
+            list.dedup();
+            let compilers = if need_compiler {
+                list.par_iter().flat_map_iter(|f| compilers(f)).collect()
             } else {
-                None
+                BTreeSet::new()
+            };
+            if !opt.built_with.is_empty()
+                && !compilers
+                    .iter()
+                    .any(|c| opt.built_with.iter().any(|r| r.matches(c)))
+            {
+                return None;
+            }
+            if opt.hardening {
+                let audited: Vec<_> = list.par_iter().filter_map(|f| hardening(f)).collect();
+                let failures: Vec<_> = opt
+                    .hardening_checks
+                    .iter()
+                    .filter_map(|&c| count_failures(&audited, c.failure(), |h| h.passes(c)))
+                    .collect();
+                if failures.is_empty() {
+                    return None;
+                }
+                problems.extend(failures);
+            }
+            if need_x86 {
+                let features: Vec<_> = list.par_iter().filter_map(|f| x86_features(f)).collect();
+                let mut failures = Vec::new();
+                if opt.cet {
+                    failures.extend(count_failures(&features, "no IBT", |f| f.ibt));
+                    failures.extend(count_failures(&features, "no SHSTK", |f| f.shstk));
+                }
+                if let Some(level) = opt.isa_level {
+                    // an unknown level is neither below nor above
+                    let known: Vec<_> = features.iter().filter_map(|f| f.isa_level).collect();
+                    let failure = format!("below x86-64-v{level}");
+                    failures.extend(count_failures(&known, &failure, |&l| l >= level));
+                }
+                if failures.is_empty() {
+                    return None;
+                }
+                problems.extend(failures);
+            }
+            if let (Some(resolver), Some((target, atoms))) = (&resolver, &target) {
+                if atoms.contains(&pkg.atom)
+                    || !list
+                        .par_iter()
+                        .any(|f| links_to(f, resolver, target, opt.direct))
+                {
+                    return None;
+                }
+            }
+            if let Some(resolver) = resolver.as_ref().filter(|_| opt.broken) {
+                // the libraries of foreign elf files are in a sysroot, not where ld.so looks
+                let missing: BTreeSet<_> = list
+                    .par_iter()
+                    .filter(|f| opt.foreign || is_native(f, &arch))
+                    .flat_map_iter(|f| missing_libs(f, resolver))
+                    .collect();
+                if missing.is_empty() {
+                    return None;
+                }
+                problems.extend(
+                    missing
+                        .into_iter()
+                        .map(|soname| format!("missing {soname}")),
+                );
             }
+            let build_time = times.get(&pkg.atom).cloned();
+            let time = build_time.as_ref().map_or(0, |t| t.get(opt.stat));
+            let deps = if rebuild {
+                pkg.entries.iter().flat_map(|e| e.dependencies()).collect()
+            } else {
+                Vec::new()
+            };
+            let prebuilt = output
+                .then(|| prebuilt::check(&pkg.atom, latest_entry(&pkg)))
+                .flatten();
+            Some(Pkg {
+                atom: pkg.atom,
+                files: list,
+                build_time,
+                time,
+                compilers,
+                problems,
+                deps,
+                flags,
+                origin,
+                prebuilt,
+            })
         })
         .collect();
-    if !opt.time && !opt.rebuild {
+    if !output {
         return;
     }
     if need_time {
-        pkgs.sort_by_key(|p| p.2);
+        pkgs.sort_by_key(|p| p.time);
+    }
+    let target = target.as_ref().map(|(target, _)| target);
+    let tiers = tiers::Tiers::new(
+        opt.tier.clone(),
+        opt.emerge_args
+            .iter()
+            .flat_map(|a| a.split_whitespace())
+            .map(|a| a.to_owned())
+            .collect(),
+        tiers::Config::read(opt.config.as_deref()),
+        &make_conf,
+        root.env(),
+    );
+    let (rebuilds, tier_of) = if rebuild {
+        plan(&pkgs, &opt, &tiers, &make_conf, &root)
+    } else {
+        Default::default()
+    };
+    if json {
+        let reports: Vec<_> = pkgs
+            .into_par_iter()
+            .map(|pkg| PackageReport {
+                files: pkg
+                    .files
+                    .par_iter()
+                    .map(|f| file_report(f, &opt, &arch, resolver.as_ref(), target))
+                    .collect(),
+                tier: tier_of.get(&pkg.atom).filter(|_| rebuild).cloned(),
+                atom: pkg.atom,
+                build_time: pkg.build_time,
+                compilers: need_compiler.then_some(pkg.compilers),
+                flags: pkg.flags,
+                origin: pkg.origin,
+                prebuilt: pkg.prebuilt,
+                problems: pkg.problems,
+            })
+            .collect();
+        report::print(opt.format, &reports, rebuild.then_some(&rebuilds[..]));
+        return;
     }
-    for (pkg, list, time) in &pkgs {
-        if need_time {
-            let t = print_time(*time);
-            println!("{pkg}: {t}");
+    for pkg in &pkgs {
+        let atom = &pkg.atom;
+        let mut line = if need_time {
+            let t = print_time(pkg.time);
+            format!("{atom}: {t}")
         } else {
-            println!("{pkg}");
+            atom.to_owned()
+        };
+        if opt.compiler && !pkg.compilers.is_empty() {
+            line += &format!(" ({})", join(&pkg.compilers));
+        }
+        if !pkg.problems.is_empty() {
+            line += &format!(" [{}]", join(&pkg.problems));
+        }
+        if let Some(origin) = pkg.origin {
+            line += &format!(" <{origin}>");
+        }
+        if let Some(reason) = pkg.prebuilt {
+            line += &format!(" <prebuilt: {reason}>");
+        }
+        println!("{line}");
+        let recorded = pkg.flags.as_ref().filter(|f| **f != BuildFlags::default());
+        if let Some(flags) = recorded.filter(|_| opt.flags) {
+            println!("{flags}");
         }
         if opt.file {
-            for f in list {
-                println!("{f}")
+            for f in &pkg.files {
+                let notes = file_report(f, &opt, &arch, resolver.as_ref(), target)
+                    .notes(opt.links_to.as_deref().unwrap_or_default());
+                if notes.is_empty() {
+                    println!("{f}");
+                } else {
+                    println!("{f}: {}", notes.join("; "));
+                }
             }
         }
     }
-    if !opt.rebuild {
-        return;
+    if rebuild {
+        let stages = rebuilds.last().map_or(0, |r| r.stage + 1);
+        for r in &rebuilds {
+            // with --schedule a command starts once those it waits for finished
+            if opt.schedule.is_some() && stages > 1 {
+                match r.after.is_empty() {
+                    true => println!("# {}", r.tier),
+                    false => println!("# {} after {}", r.tier, r.after.join(", ")),
+                }
+            }
+            println!("{}", r.command);
+        }
+        if let Some(time) = rebuilds.iter().filter_map(|r| r.estimated_time).max() {
+            println!("# estimated finish after {}", print_time(time));
+        }
     }
-    let small_pkgs:Vec<_> = pkgs.par_iter().filter_map(|p| {
-        if p.2 < 60 {
-            Some(p.0.to_owned())
-        }else {
-            None
-        }
-    }).collect();
-    let middle_pkgs: Vec<_> = pkgs.par_iter().filter_map(|p| {
-        if p.2 >= 60 && p.2 < 15*60 {
-            Some(p.0.to_owned())
-        }else {
-            None
-        }
-    }).collect();
-    let big_pkgs: Vec<_> = pkgs.par_iter().filter_map(|p| {
-        if p.2 >= 15*60 {
-            Some(p.0.to_owned())
-        }else {
-            None
-        }
-    }).collect();
-    println!("emerge -av1j16 -l20 --keep-going {}", small_pkgs.join(" "));
-    println!("emerge -av1j2 -l20 --keep-going {}", middle_pkgs.join(" "));
-    println!("emerge -av1 --keep-going {}", big_pkgs.join(" "));
 }
@@ -0,0 +1,241 @@
+//! Variables of make.conf, which portage sources as bash on top of the make.defaults of the
+//! profile
+
+use crate::root::Root;
+use color_eyre::eyre::Context;
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind as ioErrorKind,
+    iter::Peekable,
+    path::{Path, PathBuf},
+    str::Chars,
+};
+
+const MAKE_CONF_PATH: &str = "/etc/portage/make.conf";
+/// Symlink to the profile, the second one is where it used to be
+const PROFILE_PATHS: [&str; 2] = ["/etc/portage/make.profile", "/etc/make.profile"];
+/// Profile of the user, which comes last
+const USER_PROFILE_PATH: &str = "/etc/portage/profile";
+/// Where `repo:path` parents are looked up, repositories aren't read from repos.conf
+const REPOS_PATH: &str = "/var/db/repos";
+/// Parents nested deeper than this are a cycle
+const MAX_PROFILE_DEPTH: usize = 64;
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// `$NAME` or `${NAME}` after the `$`, unset variables are empty
+fn expand(chars: &mut Peekable<Chars>, vars: &HashMap<String, String>, value: &mut String) {
+    let mut name = String::new();
+    if chars.next_if_eq(&'{').is_some() {
+        for c in chars.by_ref() {
+            if c == '}' {
+                break;
+            }
+            name.push(c);
+        }
+        // `${NAME:-default}` and friends, only the name is looked at
+        name.truncate(name.find(|c| !is_name_char(c)).unwrap_or(name.len()));
+    } else {
+        while let Some(c) = chars.next_if(|&c| is_name_char(c)) {
+            name.push(c);
+        }
+        if name.is_empty() {
+            value.push('$');
+            return;
+        }
+    }
+    value.push_str(vars.get(&name).map_or("", |v| v));
+}
+
+/// Value of an assignment up to the next unquoted whitespace
+fn value(chars: &mut Peekable<Chars>, vars: &HashMap<String, String>) -> String {
+    let mut value = String::new();
+    while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+        match c {
+            '\'' => value.extend(chars.by_ref().take_while(|&c| c != '\'')),
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '$' => expand(chars, vars, &mut value),
+                        '\\' => match chars.next() {
+                            Some('\n') | None => {}
+                            Some(c @ ('"' | '\\' | '$' | '`')) => value.push(c),
+                            Some(c) => {
+                                value.push('\\');
+                                value.push(c);
+                            }
+                        },
+                        c => value.push(c),
+                    }
+                }
+            }
+            '$' => expand(chars, vars, &mut value),
+            '\\' => match chars.next() {
+                Some('\n') | None => {}
+                Some(c) => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+    value
+}
+
+/// `NAME=value` assignments, anything else on a line is skipped
+fn parse(conf: &str, vars: &mut HashMap<String, String>) {
+    let mut chars = conf.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return;
+        }
+        let mut name = String::new();
+        while let Some(c) = chars.next_if(|&c| is_name_char(c)) {
+            name.push(c);
+        }
+        if name == "export" && chars.next_if(|&c| c == ' ' || c == '\t').is_some() {
+            continue;
+        }
+        if name.is_empty() || chars.next_if_eq(&'=').is_none() {
+            // comments, `source` and everything else portage would complain about
+            chars.by_ref().find(|&c| c == '\n');
+            continue;
+        }
+        let value = value(&mut chars, vars);
+        vars.insert(name, value);
+    }
+}
+
+/// A config file, or the files of a config directory in order, skipping hidden ones
+pub fn config_files(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_owned()];
+    }
+    let mut files: Vec<_> = fs::read_dir(path)
+        .with_context(|| format!("Failed to read directory: {}", path.display()))
+        .unwrap()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            !p.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with(['.', '#'])
+        })
+        .collect();
+    files.sort();
+    files.iter().flat_map(|f| config_files(f)).collect()
+}
+
+/// Source a file of variables on top of `vars`, a missing file sets nothing
+pub fn source(path: &Path, vars: &mut HashMap<String, String>) {
+    for file in config_files(path) {
+        match fs::read(&file) {
+            Ok(conf) => parse(&String::from_utf8_lossy(&conf), vars),
+            Err(e) if e.kind() == ioErrorKind::NotFound => {}
+            Err(e) => Err(e)
+                .with_context(|| format!("Failed to read file: {}", file.display()))
+                .unwrap(),
+        }
+    }
+}
+
+/// `dir` after the profiles of its `parent` file, which come after their parents
+fn profile_dirs(root: &Root, dir: PathBuf, depth: usize, dirs: &mut Vec<PathBuf>) {
+    let path = dir.join("parent");
+    let parents = match fs::read_to_string(&path) {
+        Ok(parents) => parents,
+        Err(e) if e.kind() == ioErrorKind::NotFound => String::new(),
+        Err(e) => Err(e)
+            .with_context(|| format!("Failed to read file: {}", path.display()))
+            .unwrap(),
+    };
+    if depth < MAX_PROFILE_DEPTH {
+        for parent in parents.lines().map(str::trim) {
+            let parent = match parent.split_once(':') {
+                _ if parent.is_empty() || parent.starts_with('#') => continue,
+                // `gentoo:default/linux/amd64/23.0` of profile-formats = portage-2
+                Some((repo, path)) => {
+                    PathBuf::from(root.prefixed(&format!("{REPOS_PATH}/{repo}/profiles/{path}")))
+                }
+                None => dir.join(parent),
+            };
+            profile_dirs(root, parent, depth + 1, dirs);
+        }
+    }
+    dirs.push(dir);
+}
+
+/// Directories of the profile and its parents in the order portage reads them,
+/// empty if the system has no profile
+fn profile(root: &Root) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    let link = PROFILE_PATHS
+        .iter()
+        .map(|p| PathBuf::from(root.prefixed(p)))
+        .find(|p| p.symlink_metadata().is_ok());
+    if let Some(link) = link {
+        // an absolute target is one of the system, not of binarypkg
+        let dir = match fs::read_link(&link) {
+            Ok(target) if target.is_absolute() => {
+                PathBuf::from(root.path(&target.to_string_lossy()))
+            }
+            Ok(target) => link.parent().unwrap().join(target),
+            Err(_) => link,
+        };
+        profile_dirs(root, dir, 0, &mut dirs);
+    }
+    let user = PathBuf::from(root.prefixed(USER_PROFILE_PATH));
+    if user.is_dir() {
+        profile_dirs(root, user, 0, &mut dirs);
+    }
+    dirs
+}
+
+/// Variables set in make.conf, which may also be a directory of files read in order,
+/// on top of those of the profile, so e.g. `LDFLAGS="${LDFLAGS} -Wl,--as-needed"` has the
+/// LDFLAGS of the profile
+pub fn read(root: &Root) -> HashMap<String, String> {
+    let mut vars = HashMap::new();
+    for dir in profile(root) {
+        source(&dir.join("make.defaults"), &mut vars);
+    }
+    source(Path::new(&root.prefixed(MAKE_CONF_PATH)), &mut vars);
+    vars
+}
+
+/// `-j` of MAKEOPTS, i.e. how many threads one build uses
+pub fn make_jobs(vars: &HashMap<String, String>) -> Option<u32> {
+    option(vars.get("MAKEOPTS")?, "-j", &["--jobs"])?
+        .parse()
+        .ok()
+}
+
+/// Value of `-j` or `--jobs` style options in MAKEOPTS or EMERGE_DEFAULT_OPTS,
+/// `Some("")` if the option has no value
+pub fn option<'a>(opts: &'a str, short: &str, long: &[&str]) -> Option<&'a str> {
+    let mut found = None;
+    let mut args = opts.split_whitespace().peekable();
+    while let Some(arg) = args.next() {
+        let value = if let Some(v) = arg.strip_prefix(short).filter(|_| !arg.starts_with("--")) {
+            v
+        } else if let Some(v) = long.iter().find_map(|l| {
+            let rest = arg.strip_prefix(l)?;
+            rest.is_empty().then_some("").or(rest.strip_prefix('='))
+        }) {
+            v
+        } else {
+            continue;
+        };
+        let takes_next = |next: &&str| next.starts_with(|c: char| c.is_ascii_digit());
+        // the last one wins, like in make and emerge
+        found = Some(match value {
+            "" => args.next_if(takes_next).unwrap_or(""),
+            v => v,
+        });
+    }
+    found
+}
@@ -0,0 +1,86 @@
+//! When packages were merged, from the BUILD_TIME portage records in the VDB
+
+use crate::{root::Root, vdb};
+use std::str::FromStr;
+
+/// Days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// `2024-03-01`, `2024-03-01 12:30`, `2024-03-01T12:30:15` in UTC, or `@1709296215`
+fn parse_date(s: &str) -> Option<u64> {
+    if let Some(timestamp) = s.strip_prefix('@') {
+        return timestamp.parse().ok();
+    }
+    let (date, time) = s.split_once([' ', 'T']).unwrap_or((s, "0:0"));
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    let time: Vec<i64> = time
+        .split(':')
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    let &[year, month, day] = date.as_slice() else {
+        return None;
+    };
+    let (hour, min, sec) = match *time.as_slice() {
+        [hour, min] => (hour, min, 0),
+        [hour, min, sec] => (hour, min, sec),
+        _ => return None,
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+    let secs = days_from_civil(year, month, day) * 86400 + hour * 3600 + min * 60 + sec;
+    u64::try_from(secs).ok()
+}
+
+/// Argument of `--built-before` and `--built-after`
+#[derive(Debug, Clone)]
+pub enum Moment {
+    /// seconds since the epoch
+    Time(u64),
+    /// when the installed package (CAT/PN or PN) was merged
+    Package(String),
+}
+
+impl FromStr for Moment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(time) = parse_date(s) {
+            Ok(Moment::Time(time))
+        } else if s.starts_with(|c: char| c.is_ascii_digit() || c == '@') {
+            Err(format!("invalid date {s}"))
+        } else {
+            Ok(Moment::Package(s.to_owned()))
+        }
+    }
+}
+
+impl Moment {
+    /// Seconds since the epoch, for a package when its latest installed version was merged
+    pub fn resolve(&self, root: &Root) -> u64 {
+        match self {
+            Moment::Time(time) => *time,
+            Moment::Package(atom) => {
+                let pkgs = vdb::packages(root, Some(atom));
+                if pkgs.is_empty() {
+                    panic!("No installed package matches {atom}");
+                }
+                pkgs.iter()
+                    .flat_map(|p| &p.entries)
+                    .filter_map(|e| e.build_time())
+                    .max()
+                    .unwrap_or_else(|| panic!("No BUILD_TIME recorded for {atom}"))
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Out-of-tree kernel modules and the kernel they were built for, like `@module-rebuild`
+
+use crate::{
+    elf::{Elf, ReadAt},
+    root::Root,
+};
+use color_eyre::eyre::Context;
+use std::{
+    fs,
+    io::{ErrorKind as ioErrorKind, Result},
+};
+
+/// Written by `make` in the kernel sources, so only there if the kernel was built
+const KERNEL_RELEASE_PATH: &str = "/usr/src/linux/include/config/kernel.release";
+const OSRELEASE_PATH: &str = "/proc/sys/kernel/osrelease";
+
+/// Release of the kernel in /usr/src/linux of `root`, which modules are built against,
+/// or of the running kernel if the sources weren't built
+pub fn kernel_release(root: &Root) -> Option<String> {
+    [root.path(KERNEL_RELEASE_PATH), OSRELEASE_PATH.to_owned()]
+        .into_iter()
+        .find_map(|path| match fs::read_to_string(&path) {
+            Ok(release) => Some(release.trim().to_owned()),
+            Err(e) if e.kind() == ioErrorKind::NotFound => None,
+            Err(e) => Err(e)
+                .with_context(|| format!("Failed to read file: {path}"))
+                .unwrap(),
+        })
+}
+
+/// `.ko`, `.ko.xz`, `.ko.zst` or `.ko.gz`, but not the in-tree modules of a
+/// distribution kernel in `/lib/modules/<release>/kernel`, which belong to their kernel
+pub fn is_module(path: &str) -> bool {
+    let in_tree = path
+        .split_once("/lib/modules/")
+        .and_then(|(_, rest)| rest.split('/').nth(1))
+        == Some("kernel");
+    !in_tree
+        && [".ko", ".ko.xz", ".ko.zst", ".ko.gz"]
+            .iter()
+            .any(|e| path.ends_with(e))
+}
+
+/// Kernel release of `vermagic=` in `.modinfo`, e.g. `6.6.13-gentoo` of
+/// `6.6.13-gentoo SMP preempt mod_unload modversions`
+pub fn release<R: ReadAt>(elf: &Elf<R>) -> Result<Option<String>> {
+    let Some(modinfo) = elf.section_data(".modinfo")? else {
+        return Ok(None);
+    };
+    let vermagic = modinfo
+        .split(|&b| b == 0)
+        .find_map(|s| s.strip_prefix(b"vermagic="))
+        .map(|v| String::from_utf8_lossy(v).into_owned());
+    Ok(vermagic.and_then(|v| Some(v.split_whitespace().next()?.to_owned())))
+}
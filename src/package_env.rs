@@ -0,0 +1,75 @@
+//! Per-package environment of /etc/portage/package.env and the files of /etc/portage/env
+
+use crate::{atom::Atom, make_conf, root::Root, vdb::Entry};
+use color_eyre::eyre::Context;
+use std::{collections::HashMap, fs, io::ErrorKind as ioErrorKind, path::Path};
+
+const PACKAGE_ENV_PATH: &str = "/etc/portage/package.env";
+const ENV_DIR: &str = "/etc/portage/env";
+
+/// `atom env-file...` lines of package.env
+pub struct PackageEnv {
+    lines: Vec<(Atom, Vec<String>)>,
+    /// /etc/portage/env of the system
+    env_dir: String,
+}
+
+impl PackageEnv {
+    /// package.env may also be a directory of files read in order
+    pub fn read(root: &Root) -> Self {
+        let env_dir = root.prefixed(ENV_DIR);
+        let mut lines = Vec::new();
+        for file in make_conf::config_files(Path::new(&root.prefixed(PACKAGE_ENV_PATH))) {
+            let conf = match fs::read_to_string(&file) {
+                Ok(conf) => conf,
+                Err(e) if e.kind() == ioErrorKind::NotFound => continue,
+                Err(e) => Err(e)
+                    .with_context(|| format!("Failed to read file: {}", file.display()))
+                    .unwrap(),
+            };
+            for (n, line) in conf.lines().enumerate() {
+                let mut fields = line.split('#').next().unwrap().split_whitespace();
+                let Some(atom) = fields.next() else {
+                    continue;
+                };
+                let atom = match atom.parse() {
+                    Ok(atom) => atom,
+                    Err(e) => {
+                        eprintln!("Skipping {}:{}: {e}", file.display(), n + 1);
+                        continue;
+                    }
+                };
+                let files: Vec<_> = fields.map(|f| f.to_owned()).collect();
+                for f in files
+                    .iter()
+                    .filter(|f| !Path::new(&env_dir).join(f).exists())
+                {
+                    eprintln!("Missing env file {f} at {}:{}", file.display(), n + 1);
+                }
+                lines.push((atom, files));
+            }
+        }
+        PackageEnv { lines, env_dir }
+    }
+
+    /// Env files applying to an installed version of `atom` (CAT/PN), in the order portage
+    /// sources them
+    pub fn files(&self, atom: &str, entry: &Entry) -> Vec<&str> {
+        let slot = entry.slot();
+        let repo = entry.repository();
+        self.lines
+            .iter()
+            .filter(|(a, _)| a.matches(atom, entry.version(), slot.as_deref(), repo.as_deref()))
+            .flat_map(|(_, files)| files.iter().map(|f| f.as_str()))
+            .collect()
+    }
+
+    /// `vars` of make.conf with the env files sourced on top
+    pub fn apply(&self, files: &[&str], vars: &HashMap<String, String>) -> HashMap<String, String> {
+        let mut vars = vars.clone();
+        for file in files {
+            make_conf::source(&Path::new(&self.env_dir).join(file), &mut vars);
+        }
+        vars
+    }
+}
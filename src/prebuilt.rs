@@ -0,0 +1,67 @@
+//! Packages installing prebuilt binaries, which rebuilding only installs again
+
+use crate::vdb::Entry;
+use serde::Serialize;
+use std::fmt;
+
+/// Why a package is considered prebuilt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// PN ends with `-bin`, the naming convention for binary packages
+    Bin,
+    /// the ebuild sets `QA_PREBUILT` for files it doesn't build
+    QaPrebuilt,
+    /// neither the ebuild nor its eclasses define `src_configure` or `src_compile`, only a
+    /// label, as the EAPI's default `src_compile` may have run make
+    NoCompile,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Reason::Bin => "-bin package",
+            Reason::QaPrebuilt => "QA_PREBUILT",
+            Reason::NoCompile => "no src_compile",
+        })
+    }
+}
+
+/// `declare -x QA_PREBUILT="opt/foo/*"` or `QA_PREBUILT='...'` with a value other than empty
+fn sets_qa_prebuilt(env: &str) -> bool {
+    env.lines().any(|line| {
+        let line = match line.strip_prefix("declare ") {
+            Some(rest) => rest.split_once(' ').map_or("", |(_, rest)| rest),
+            None => line,
+        };
+        line.strip_prefix("QA_PREBUILT=")
+            .is_some_and(|value| !matches!(value, "" | "\"\"" | "''"))
+    })
+}
+
+impl Reason {
+    /// Whether rebuilding the package installs the same binaries again, so `--rebuild` leaves
+    /// it out unless `--prebuilt` is given
+    pub fn is_prebuilt(self) -> bool {
+        self != Reason::NoCompile
+    }
+}
+
+/// `None` if the package of `entry`, given as CAT/PN, is built from source. Checks the name
+/// first, as reading `environment.bz2` means decompressing it.
+pub fn check(atom: &str, entry: &Entry) -> Option<Reason> {
+    if atom.ends_with("-bin") {
+        return Some(Reason::Bin);
+    }
+    if entry
+        .environment()
+        .is_some_and(|env| sets_qa_prebuilt(&env))
+    {
+        return Some(Reason::QaPrebuilt);
+    }
+    // old entries don't have DEFINED_PHASES, which doesn't mean nothing was compiled
+    let phases = entry.defined_phases();
+    phases
+        .is_some_and(|p| !p.iter().any(|p| p == "configure" || p == "compile"))
+        .then_some(Reason::NoCompile)
+}
@@ -0,0 +1,228 @@
+//! Machine readable output of `--format json` and `--format jsonl`.
+//!
+//! `json` prints one document:
+//!
+//! ```text
+//! {
+//!   "schema_version": 1,
+//!   "packages": [<package>, ...],
+//!   "rebuild": [<rebuild>, ...]          only with --rebuild and the options implying it
+//! }
+//! ```
+//!
+//! `jsonl` prints one object per line, `<package>` or `<rebuild>` with two more fields:
+//! `"schema_version": 1` and `"type": "package"` or `"type": "rebuild"`.
+//!
+//! A `<package>` is
+//!
+//! ```text
+//! {
+//!   "atom": "sys-apps/coreutils",
+//!   "build_time": {"average": 90, "median": 90, "min": 80, "max": 100, "last": 100},
+//!                                        seconds, null if emerge.log has no complete build
+//!   "tier": "small",                     only with --rebuild, name of the tier, see <rebuild>
+//!   "compilers": [{"name": "gcc", "version": "13.2.1"}],    only with --compiler or --built-with
+//!   "flags": {"cflags": "-O2 -pipe", "cxxflags": "-O2 -pipe", "ldflags": "-Wl,-O1",
+//!             "chost": "x86_64-pc-linux-gnu", "cbuild": null},
+//!                                        recorded in the VDB, only with --flags, --flags-differ,
+//!                                        --env-drift or --missing-flag
+//!   "origin": "local",                   local, binhost or gentoo, where the package was built,
+//!                                        only with --origin or --installed-from
+//!   "prebuilt": "qa_prebuilt",           bin or qa_prebuilt if the package installs prebuilt
+//!                                        binaries, which --rebuild leaves out unless --prebuilt
+//!                                        is given, no_compile if it has no compile phase, which
+//!                                        is only a label
+//!   "problems": ["no PIE 2/5"],          why the package was selected by a filter
+//!   "files": [<file>, ...]
+//! }
+//! ```
+//!
+//! A `<file>` is
+//!
+//! ```text
+//! {
+//!   "path": "/usr/bin/ls",             `/usr/lib64/libfoo.a(foo.o)` for archive members
+//!                                        and with the directory of --root in front
+//!   "kind": "pie",                       pie, exec, lib, object, module, foreign or other,
+//!                                        null if the file isn't ELF
+//!   "compression": "xz",                 xz, zstd or gzip, only if the file is compressed, the
+//!                                        other fields describe the decompressed file
+//!   "elf": {"class": "elf64", "endian": "little", "osabi": 0, "type": "dyn", "machine": 62,
+//!           "interp": "/lib64/ld-linux-x86-64.so.2", "pie": true},
+//!   "compilers": [...],                  only with --compiler
+//!   "hardening": {"pie": true, "relro": "full", "bind_now": true, "canary": true,
+//!                 "fortify": true, "nx": true},            only with --hardening
+//!   "x86": {"ibt": true, "shstk": true, "isa_level": 3},   only with --cet or --isa-level
+//!   "lto": [{"name": "gcc", "version": "13.2.1"}],
+//!                                        compilers which wrote LTO bytecode, only with --lto
+//!                                        and if the file has any
+//!   "kernel": "6.6.13-gentoo",           release of the kernel module's vermagic, only with
+//!                                        --modules
+//!   "links_to": true,                    only with --links-to
+//!   "missing": ["libfoo.so.1"]           only with --broken
+//! }
+//! ```
+//!
+//! `"type"` of `elf` is `rel`, `exec`, `dyn`, `core` or `{"other": <e_type>}`, `"pie"` of
+//! `hardening` is null for shared libraries. A `<rebuild>` is
+//! `{"tier": "small", "stage": 0, "command": "emerge -av1j16 -l20 --keep-going ..."}`, tiers are
+//! `small`, `middle` and `big` unless configured otherwise. Packages are rebuilt after their
+//! dependencies: commands run in order, and a stage only starts after the previous one.
+//! With `--schedule` the commands are meant to run at the same time, their tiers are `job1`,
+//! `job2` and so on, a command only waits for those in its `"after"`, like `["job1"]`, which
+//! rebuild its dependencies, and they have `"estimated_time"`, the seconds from the start of the
+//! rebuild until the command finishes.
+//!
+//! Fields only present with some options are left out otherwise. New fields may be added
+//! without notice, `schema_version` is increased when a field is removed or changes meaning.
+
+use crate::{
+    binpkg::Origin, cet::X86Features, compiler::Compiler, compress::Compression, elf::ElfInfo,
+    emerge_log::BuildTime, flags::BuildFlags, hardening::Hardening, join, kind::Kind,
+    prebuilt::Reason,
+};
+use clap::ArgEnum;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum)]
+pub enum Format {
+    Text,
+    Json,
+    Jsonl,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+    pub path: String,
+    pub kind: Option<Kind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elf: Option<ElfInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compilers: Option<BTreeSet<Compiler>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hardening: Option<Hardening>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x86: Option<X86Features>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lto: Option<BTreeSet<Compiler>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kernel: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub links_to: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub missing: Option<Vec<String>>,
+}
+
+impl FileReport {
+    /// What the text format prints after the path
+    pub fn notes(&self, links_to: &str) -> Vec<String> {
+        let mut notes = Vec::new();
+        notes.extend(self.kind.map(|k| k.to_string()));
+        notes.extend(self.compression.map(|c| match self.kind {
+            Some(_) => format!("compressed ELF ({c})"),
+            None => format!("{c} compressed"),
+        }));
+        notes.extend(self.elf.as_ref().map(|i| i.to_string()));
+        notes.extend(self.compilers.as_ref().filter(|c| !c.is_empty()).map(join));
+        notes.extend(self.hardening.as_ref().map(|h| h.to_string()));
+        notes.extend(self.x86.as_ref().map(|x| x.to_string()));
+        notes.extend(self.lto.as_ref().map(|c| match c.is_empty() {
+            true => "LTO bytecode".to_owned(),
+            false => format!("LTO bytecode of {}", join(c)),
+        }));
+        notes.extend(self.kernel.as_ref().map(|k| format!("built for {k}")));
+        if self.links_to == Some(true) {
+            notes.push(format!("links to {links_to}"));
+        }
+        if let Some(missing) = self.missing.as_ref().filter(|m| !m.is_empty()) {
+            notes.push(format!("missing {}", join(missing)));
+        }
+        notes
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageReport {
+    pub atom: String,
+    pub build_time: Option<BuildTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compilers: Option<BTreeSet<Compiler>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flags: Option<BuildFlags>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<Origin>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prebuilt: Option<Reason>,
+    pub problems: Vec<String>,
+    pub files: Vec<FileReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RebuildReport {
+    pub tier: String,
+    pub command: String,
+    /// commands of a stage run after those of the previous stage
+    pub stage: u32,
+    /// tiers of the commands rebuilding dependencies, which have to finish first, with --schedule
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<String>,
+    /// seconds from the start of the rebuild until the command finishes, with --schedule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_time: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    schema_version: u32,
+    packages: &'a [PackageReport],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rebuild: Option<&'a [RebuildReport]>,
+}
+
+#[derive(Serialize)]
+struct Line<'a, T> {
+    schema_version: u32,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(flatten)]
+    data: &'a T,
+}
+
+/// Print in `json` or `jsonl` format
+pub fn print(format: Format, packages: &[PackageReport], rebuild: Option<&[RebuildReport]>) {
+    match format {
+        Format::Json => {
+            let document = Document {
+                schema_version: SCHEMA_VERSION,
+                packages,
+                rebuild,
+            };
+            println!("{}", serde_json::to_string_pretty(&document).unwrap());
+        }
+        Format::Jsonl => {
+            for package in packages {
+                print_line("package", package);
+            }
+            for rebuild in rebuild.unwrap_or_default() {
+                print_line("rebuild", rebuild);
+            }
+        }
+        Format::Text => unreachable!("text is printed by main"),
+    }
+}
+
+fn print_line<T: Serialize>(kind: &'static str, data: &T) {
+    let line = Line {
+        schema_version: SCHEMA_VERSION,
+        kind,
+        data,
+    };
+    println!("{}", serde_json::to_string(&line).unwrap());
+}
@@ -0,0 +1,57 @@
+//! The system being inspected, `ROOT` and `EPREFIX` like portage uses them: `ROOT` is where it
+//! is mounted, e.g. a chroot, and `EPREFIX` the offset of a Gentoo Prefix inside of it
+
+/// Both without trailing `/`, empty for the host
+#[derive(Debug, Clone, Default)]
+pub struct Root {
+    root: String,
+    eprefix: String,
+}
+
+impl Root {
+    pub fn new(root: Option<&str>, eprefix: Option<&str>) -> Self {
+        let trim = |dir: Option<&str>| dir.unwrap_or_default().trim_end_matches('/').to_owned();
+        Root {
+            root: trim(root),
+            eprefix: trim(eprefix),
+        }
+    }
+
+    /// Whether this is the system binarypkg runs on
+    pub fn is_host(&self) -> bool {
+        self.root.is_empty() && self.eprefix.is_empty()
+    }
+
+    /// A path as the system sees it, e.g. of CONTENTS, which already includes `EPREFIX`.
+    /// Relative paths are left alone.
+    pub fn path(&self, path: &str) -> String {
+        match path.starts_with('/') {
+            true => format!("{}{path}", self.root),
+            false => path.to_owned(),
+        }
+    }
+
+    /// A path of portage or the toolchain, which live below `EPREFIX`, e.g. `/var/db/pkg`
+    pub fn prefixed(&self, path: &str) -> String {
+        format!("{}{}{path}", self.root, self.eprefix)
+    }
+
+    /// A path as the system sees it of `path` below `ROOT`
+    pub fn strip<'a>(&self, path: &'a str) -> &'a str {
+        path.strip_prefix(&self.root)
+            .filter(|p| p.starts_with('/'))
+            .unwrap_or(path)
+    }
+
+    /// `ROOT=` and `EPREFIX=` for emerge commands, empty for the host
+    pub fn env(&self) -> Vec<String> {
+        let mut env = Vec::new();
+        if !self.root.is_empty() {
+            env.push(format!("ROOT={}", self.root));
+        }
+        if !self.eprefix.is_empty() {
+            env.push(format!("EPREFIX={}", self.eprefix));
+        }
+        env
+    }
+}
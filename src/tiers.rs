@@ -0,0 +1,240 @@
+//! Rebuild tiers: packages are grouped by build time, each group gets its own emerge command line
+
+use crate::make_conf;
+use color_eyre::eyre::Context;
+use std::{collections::HashMap, fs, io::ErrorKind as ioErrorKind, str::FromStr, thread};
+
+const CONFIG_PATH: &str = "/etc/portage/binarypkg.conf";
+
+/// One group of packages emerged with one command line
+#[derive(Debug, Clone)]
+pub struct Tier {
+    pub name: String,
+    /// packages building for less seconds than this belong to the tier, `None` for no limit
+    max_time: Option<u64>,
+    /// `--jobs`, emerge runs one job at a time if `None`
+    jobs: Option<u32>,
+    /// `--load-average`, the one derived from make.conf if `None`, no limit if 0
+    load_average: Option<f64>,
+    /// extra emerge arguments
+    args: Vec<String>,
+}
+
+/// `90`, `90s`, `15m`, `2h` => seconds
+fn parse_duration(s: &str) -> Result<u64, String> {
+    let (n, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let n: u64 = n.parse().map_err(|_| format!("invalid duration {s}"))?;
+    match unit {
+        "s" => Ok(n),
+        "m" => Ok(n * 60),
+        "h" => Ok(n * 60 * 60),
+        _ => Err(format!("invalid duration {s}")),
+    }
+}
+
+/// `-` leaves a field unset
+fn field<T: FromStr>(field: Option<&str>, what: &str) -> Result<Option<T>, String> {
+    match field {
+        None | Some("-") => Ok(None),
+        Some(f) => f
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("invalid {what} {f}")),
+    }
+}
+
+/// `NAME MAX_TIME [JOBS [LOAD_AVERAGE [EMERGE_ARGS...]]]`, e.g. `small 1m 16 20`
+impl FromStr for Tier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split_whitespace();
+        let name = fields.next().ok_or("empty tier")?.to_owned();
+        let max_time = match fields.next() {
+            None => return Err(format!("missing time limit of tier {name}")),
+            Some("-") => None,
+            Some(t) => Some(parse_duration(t)?),
+        };
+        Ok(Tier {
+            name,
+            max_time,
+            jobs: field(fields.next(), "jobs")?.filter(|&j| j > 1),
+            load_average: field(fields.next(), "load average")?,
+            args: fields.map(|a| a.to_owned()).collect(),
+        })
+    }
+}
+
+impl Tier {
+    /// A tier without time limit emerging one package at a time
+    pub fn serial(name: String) -> Self {
+        Tier {
+            name,
+            max_time: None,
+            jobs: None,
+            load_average: None,
+            args: Vec::new(),
+        }
+    }
+
+    /// e.g. `emerge -av1j16 -l20 --keep-going cat/pn ...`
+    pub fn command(&self, load_average: Option<f64>, args: &[String], atoms: &[&str]) -> String {
+        let mut command = String::from("emerge -av1");
+        if let Some(jobs) = self.jobs {
+            command += &format!("j{jobs}");
+            match self.load_average.or(load_average) {
+                Some(load) if load > 0.0 => command += &format!(" -l{load}"),
+                _ => {}
+            }
+        }
+        command += " --keep-going";
+        for arg in self
+            .args
+            .iter()
+            .chain(args)
+            .map(|a| a.as_str())
+            .chain(atoms.iter().copied())
+        {
+            command.push(' ');
+            command += arg;
+        }
+        command
+    }
+}
+
+/// Rebuild settings of the config file and the command line
+#[derive(Debug, Default)]
+pub struct Config {
+    pub tiers: Vec<Tier>,
+    /// extra arguments of every emerge command line
+    pub emerge_args: Vec<String>,
+}
+
+impl Config {
+    /// `tier <TIER>` and `emerge-args <ARGS>` lines, `#` starts a comment. The file at the
+    /// default location is optional, one given with `--config` isn't.
+    pub fn read(path: Option<&str>) -> Self {
+        let explicit = path.is_some();
+        let path = path.unwrap_or(CONFIG_PATH);
+        let conf = match fs::read_to_string(path) {
+            Ok(conf) => conf,
+            Err(e) if e.kind() == ioErrorKind::NotFound && !explicit => return Config::default(),
+            Err(e) => Err(e)
+                .with_context(|| format!("Failed to read file: {path}"))
+                .unwrap(),
+        };
+        let mut config = Config::default();
+        for (n, line) in conf.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            let (key, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            match key {
+                "" => {}
+                "tier" => config.tiers.push(
+                    value
+                        .parse()
+                        .unwrap_or_else(|e| panic!("Invalid tier at {path}:{}: {e}", n + 1)),
+                ),
+                "emerge-args" => config
+                    .emerge_args
+                    .extend(value.split_whitespace().map(|a| a.to_owned())),
+                _ => panic!("Unknown setting {key} at {path}:{}", n + 1),
+            }
+        }
+        config
+    }
+}
+
+/// Number of CPUs available to builds
+pub fn cores() -> u32 {
+    thread::available_parallelism().map_or(1, |n| n.get() as u32)
+}
+
+/// The original 60s and 15min tiers, with jobs scaled to MAKEOPTS,
+/// so `-j16` gives `-j16`, `-j2` and one job at a time
+fn default_tiers(make_conf: &HashMap<String, String>) -> Vec<Tier> {
+    let emerge_opts = make_conf.get("EMERGE_DEFAULT_OPTS").map_or("", |o| o);
+    let threads = make_conf::make_jobs(make_conf).unwrap_or_else(cores);
+    let jobs = make_conf::option(emerge_opts, "-j", &["--jobs"])
+        .and_then(|j| j.parse().ok())
+        .unwrap_or(threads);
+    let tier = |name: &str, max_time, jobs: u32| Tier {
+        name: name.to_owned(),
+        max_time,
+        jobs: Some(jobs).filter(|&j| j > 1),
+        load_average: None,
+        args: Vec::new(),
+    };
+    vec![
+        tier("small", Some(60), jobs),
+        tier("middle", Some(15 * 60), (threads / 8).max(2).min(jobs)),
+        tier("big", None, 1),
+    ]
+}
+
+/// `--load-average` of tiers which don't set one, from make.conf
+fn default_load_average(make_conf: &HashMap<String, String>) -> Option<f64> {
+    let makeopts = make_conf.get("MAKEOPTS").map_or("", |m| m);
+    let emerge_opts = make_conf.get("EMERGE_DEFAULT_OPTS").map_or("", |o| o);
+    make_conf::option(emerge_opts, "-l", &["--load-average"])
+        .or_else(|| make_conf::option(makeopts, "-l", &["--load-average", "--max-load"]))
+        .and_then(|l| l.parse().ok())
+        .or_else(|| Some(make_conf::make_jobs(make_conf)? as f64 * 1.25))
+}
+
+/// Tiers sorted by time limit, with the load average for tiers not setting one
+pub struct Tiers {
+    tiers: Vec<Tier>,
+    load_average: Option<f64>,
+    emerge_args: Vec<String>,
+    /// `VAR=value` in front of every emerge command line, e.g. `ROOT=/mnt/gentoo`
+    env: Vec<String>,
+}
+
+impl Tiers {
+    /// Tiers of the command line, or else the config file, or else derived from make.conf
+    pub fn new(
+        tiers: Vec<Tier>,
+        emerge_args: Vec<String>,
+        config: Config,
+        make_conf: &HashMap<String, String>,
+        env: Vec<String>,
+    ) -> Self {
+        let mut tiers = match tiers {
+            t if !t.is_empty() => t,
+            _ if !config.tiers.is_empty() => config.tiers,
+            _ => default_tiers(make_conf),
+        };
+        tiers.sort_by_key(|t| t.max_time.unwrap_or(u64::MAX));
+        let mut args = config.emerge_args;
+        args.extend(emerge_args);
+        Tiers {
+            tiers,
+            load_average: default_load_average(make_conf),
+            emerge_args: args,
+            env,
+        }
+    }
+
+    /// Index of the first tier whose time limit is above `time`, the last one if there is none
+    pub fn position(&self, time: u64) -> usize {
+        self.tiers
+            .iter()
+            .position(|t| t.max_time.is_none_or(|max| time < max))
+            .unwrap_or(self.tiers.len() - 1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Tier> {
+        self.tiers.iter()
+    }
+
+    pub fn command(&self, tier: &Tier, atoms: &[&str]) -> String {
+        let command = tier.command(self.load_average, &self.emerge_args, atoms);
+        match self.env.is_empty() {
+            true => command,
+            false => format!("{} {command}", self.env.join(" ")),
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use crate::root::Root;
+use bzip2::read::BzDecoder;
+use color_eyre::eyre::Context;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{ErrorKind as ioErrorKind, Read},
+    path::{Path, PathBuf},
+};
+
+const VDB_PATH: &str = "/var/db/pkg";
+
+/// An installed package, i.e. every installed version (slot) of one CAT/PN
+#[derive(Debug)]
+pub struct Package {
+    /// CAT/PN
+    pub atom: String,
+    pub entries: Vec<Entry>,
+}
+
+/// One installed version, i.e. `/var/db/pkg/<cat>/<pf>`
+#[derive(Debug)]
+pub struct Entry {
+    pub dir: PathBuf,
+    /// of the system the entry belongs to
+    pub root: Root,
+}
+
+/// One line of the CONTENTS file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Content {
+    Dir(String),
+    Obj(String),
+    /// path and link target
+    Sym(String, String),
+}
+
+impl Entry {
+    /// Read a metadata file of this entry, `None` if portage didn't record it
+    pub fn read(&self, name: &str) -> Option<String> {
+        let path = self.dir.join(name);
+        match fs::read_to_string(&path) {
+            Ok(s) => Some(s),
+            Err(e) if e.kind() == ioErrorKind::NotFound => None,
+            Err(e) => Err(e)
+                .with_context(|| format!("Failed to read file: {}", path.display()))
+                .unwrap(),
+        }
+    }
+
+    pub fn contents(&self) -> Vec<Content> {
+        self.read("CONTENTS")
+            .map(|s| s.lines().filter_map(parse_content).collect())
+            .unwrap_or_default()
+    }
+
+    /// Path of every regular file installed by this entry, below `ROOT`
+    pub fn objs(&self) -> Vec<String> {
+        self.contents()
+            .into_iter()
+            .filter_map(|c| match c {
+                Content::Obj(path) => Some(self.root.path(&path)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Paths of the ELF objects portage recorded in NEEDED.ELF.2, below `ROOT` like those of
+    /// `objs`, `None` if the entry has no NEEDED.ELF.2
+    pub fn needed_elf(&self) -> Option<Vec<String>> {
+        self.read("NEEDED.ELF.2").map(|s| {
+            s.lines()
+                .filter_map(needed_elf_path)
+                .map(|path| self.root.path(path))
+                .collect()
+        })
+    }
+
+    /// PVR, e.g. `1.2_rc3-r1`
+    pub fn version(&self) -> &str {
+        let pf = self.dir.file_name().unwrap().to_str().unwrap();
+        split_pf(pf).unwrap().1
+    }
+
+    /// CAT/PF, e.g. `sys-apps/coreutils-9.4-r1`
+    pub fn cpv(&self) -> String {
+        let pf = self.dir.file_name().unwrap().to_str().unwrap();
+        let category = self.dir.parent().unwrap().file_name().unwrap();
+        format!("{}/{pf}", category.to_str().unwrap())
+    }
+
+    /// BUILD_ID of the binary package this entry was installed from, if it was
+    pub fn build_id(&self) -> Option<String> {
+        Some(self.read("BUILD_ID")?.trim().to_owned())
+    }
+
+    /// MD5 of the binary package this entry was installed from, if it was
+    pub fn binpkg_md5(&self) -> Option<String> {
+        Some(self.read("BINPKGMD5")?.trim().to_owned())
+    }
+
+    /// SLOT without the sub-slot
+    pub fn slot(&self) -> Option<String> {
+        let slot = self.read("SLOT")?;
+        Some(slot.trim().split('/').next().unwrap().to_owned())
+    }
+
+    /// Repository the package came from, e.g. `gentoo`
+    pub fn repository(&self) -> Option<String> {
+        Some(self.read("repository")?.trim().to_owned())
+    }
+
+    /// When this entry was merged, in seconds since the epoch
+    pub fn build_time(&self) -> Option<u64> {
+        self.read("BUILD_TIME")?.trim().parse().ok()
+    }
+
+    /// Phase functions the ebuild or its eclasses define, e.g. `compile install`,
+    /// `None` if portage didn't record them
+    pub fn defined_phases(&self) -> Option<Vec<String>> {
+        let phases = self.read("DEFINED_PHASES")?;
+        Some(phases.split_whitespace().map(|p| p.to_owned()).collect())
+    }
+
+    /// The bash environment saved after the package was built, from `environment.bz2`
+    pub fn environment(&self) -> Option<String> {
+        let path = self.dir.join("environment.bz2");
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ioErrorKind::NotFound => return None,
+            Err(e) => Err(e)
+                .with_context(|| format!("Failed to open file: {}", path.display()))
+                .unwrap(),
+        };
+        let mut env = Vec::new();
+        match BzDecoder::new(file).read_to_end(&mut env) {
+            Ok(_) => Some(String::from_utf8_lossy(&env).into_owned()),
+            // a truncated or corrupt file doesn't tell anything
+            Err(e) if e.kind() == ioErrorKind::InvalidData => None,
+            Err(e) if e.kind() == ioErrorKind::UnexpectedEof => None,
+            Err(e) => Err(e)
+                .with_context(|| format!("Failed to read file: {}", path.display()))
+                .unwrap(),
+        }
+    }
+
+    /// CAT/PN of every package in DEPEND, RDEPEND and BDEPEND,
+    /// which portage records with USE conditionals already evaluated
+    pub fn dependencies(&self) -> Vec<String> {
+        ["DEPEND", "RDEPEND", "BDEPEND"]
+            .iter()
+            .filter_map(|name| self.read(name))
+            .flat_map(|deps| {
+                deps.split_whitespace()
+                    .filter_map(dep_atom)
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+/// `>=dev-libs/foo-1.2:0/1=[bar]` => `dev-libs/foo`, `None` for blockers, `||`, `(`, `)` and `use?`
+fn dep_atom(dep: &str) -> Option<String> {
+    if dep.starts_with('!') || !dep.contains('/') {
+        return None;
+    }
+    let versioned = dep.starts_with(['<', '>', '=', '~']);
+    let dep = dep.trim_start_matches(['<', '>', '=', '~']);
+    let dep = dep.split(['[', ':']).next().unwrap().trim_end_matches('*');
+    let (cat, pn) = dep.split_once('/')?;
+    let pn = if versioned { split_pf(pn)?.0 } else { pn };
+    Some(format!("{cat}/{pn}"))
+}
+
+/// `arch;path;soname;rpath;needed;multilib` => `path`, only the path is of interest,
+/// the libraries are resolved from the ELF files themselves
+fn needed_elf_path(line: &str) -> Option<&str> {
+    let fields: Vec<_> = line.split(';').collect();
+    (fields.len() >= 5).then(|| fields[1])
+}
+
+/// obj lines end with md5 and mtime, sym lines end with mtime,
+/// paths themselves may contain spaces.
+fn parse_content(line: &str) -> Option<Content> {
+    let (kind, rest) = line.split_once(' ')?;
+    match kind {
+        "dir" => Some(Content::Dir(rest.to_owned())),
+        "obj" => {
+            let (rest, _mtime) = rest.rsplit_once(' ')?;
+            let (path, _md5) = rest.rsplit_once(' ')?;
+            Some(Content::Obj(path.to_owned()))
+        }
+        "sym" => {
+            let (rest, _mtime) = rest.rsplit_once(' ')?;
+            let (path, target) = rest.split_once(" -> ")?;
+            Some(Content::Sym(path.to_owned(), target.to_owned()))
+        }
+        _ => None,
+    }
+}
+
+fn read_dir_names(path: &Path) -> Vec<String> {
+    let mut names: Vec<_> = fs::read_dir(path)
+        .with_context(|| format!("Failed to read dir: {}", path.display()))
+        .unwrap()
+        .filter_map(|e| {
+            let e = e.unwrap();
+            if !e.file_type().unwrap().is_dir() {
+                return None;
+            }
+            let name = e.file_name().into_string().ok()?;
+            // skip portage's temporary -MERGING- entries and hidden files
+            if name.starts_with('-') || name.starts_with('.') {
+                None
+            } else {
+                Some(name)
+            }
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Split PF into PN and PVR, e.g. `foo-bar-1.2_rc3-r1` => (`foo-bar`, `1.2_rc3-r1`)
+pub fn split_pf(pf: &str) -> Option<(&str, &str)> {
+    pf.match_indices('-')
+        .map(|(i, _)| i)
+        .find(|&i| is_version(&pf[i + 1..]))
+        .map(|i| (&pf[..i], &pf[i + 1..]))
+}
+
+fn is_version(v: &str) -> bool {
+    let (v, rev) = match v.rsplit_once("-r") {
+        Some((v, rev)) => (v, Some(rev)),
+        None => (v, None),
+    };
+    if let Some(rev) = rev {
+        if rev.is_empty() || !rev.bytes().all(|b| b.is_ascii_digit()) {
+            return false;
+        }
+    }
+    let mut parts = v.split('_');
+    let base = parts.next().unwrap();
+    let base = base
+        .strip_suffix(|c: char| c.is_ascii_lowercase())
+        .unwrap_or(base);
+    if base.is_empty()
+        || !base
+            .split('.')
+            .all(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return false;
+    }
+    parts.all(|suffix| {
+        ["alpha", "beta", "pre", "rc", "p"].iter().any(|s| {
+            suffix
+                .strip_prefix(s)
+                .map(|n| n.bytes().all(|b| b.is_ascii_digit()))
+                .unwrap_or(false)
+        })
+    })
+}
+
+/// List installed packages sorted by category and name,
+/// only the packages matching `atom` (CAT/PN or PN) if given.
+pub fn packages(root: &Root, atom: Option<&str>) -> Vec<Package> {
+    let vdb = root.prefixed(VDB_PATH);
+    let vdb = Path::new(&vdb);
+    let mut pkgs: Vec<Package> = Vec::new();
+    for category in read_dir_names(vdb) {
+        let mut entries: Vec<(String, Entry)> = read_dir_names(&vdb.join(&category))
+            .into_iter()
+            .filter_map(|pf| {
+                let pn = split_pf(&pf)?.0.to_owned();
+                let dir = vdb.join(&category).join(&pf);
+                let root = root.clone();
+                let entry = Entry { dir, root };
+                Some((pn, entry))
+            })
+            .filter(|(pn, _)| match atom {
+                Some(atom) => match atom.split_once('/') {
+                    Some((cat, name)) => cat == category && name == pn,
+                    None => atom == pn,
+                },
+                None => true,
+            })
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        for (pn, entry) in entries {
+            let atom = format!("{category}/{pn}");
+            match pkgs.last_mut() {
+                Some(last) if last.atom == atom => last.entries.push(entry),
+                _ => pkgs.push(Package {
+                    atom,
+                    entries: vec![entry],
+                }),
+            }
+        }
+    }
+    pkgs
+}
+
+/// The CHOST recorded by most packages, `None` if no package recorded one
+pub fn chost(root: &Root) -> Option<String> {
+    let mut count: BTreeMap<String, usize> = BTreeMap::new();
+    for pkg in packages(root, None) {
+        for chost in pkg.entries.iter().filter_map(|e| e.read("CHOST")) {
+            let chost = chost.trim();
+            if !chost.is_empty() {
+                *count.entry(chost.to_owned()).or_default() += 1;
+            }
+        }
+    }
+    count
+        .into_iter()
+        .max_by_key(|&(_, n)| n)
+        .map(|(chost, _)| chost)
+}